@@ -1,4 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::ops::Range;
+use std::path::Path;
 
 /* Starting with Dictionary of Keys impl. To support efficient operations,
      should eventually move to compressed sparse row/col
@@ -7,19 +11,128 @@ use std::collections::HashMap;
 pub struct SparseMatrix {
     pub shape: (u64, u64),
     values: HashMap<(u64, u64), f64>,
+    keep_explicit_zeros: bool,
 
     compressed_updated: bool,
+    // Rows touched by insert/clear_at since the CSR arrays were last current; only
+    // meaningful while rows_tracked, i.e. no untracked edit has happened since
+    dirty_rows: HashSet<u64>,
+    rows_tracked: bool,
     pub compressed_rowarray: Vec<u64>,
     pub compressed_colarray: Vec<u64>,
     pub compressed_dataarray: Vec<f64>,
+    // Diagonal (DIA) copy of a narrow, well-filled band, built alongside the CSR arrays:
+    // (half bandwidth, band) with diagonal k stored contiguously, so
+    // band[k * rows + row] = A[row][row + k - half]
+    band: Option<(usize, Vec<f64>)>,
 
     #[allow(dead_code)]
     row_iter_idx: usize,
 }
 
+// PA = LU, kept so repeated right-hand sides skip the elimination
+pub struct LuFactorization {
+    lu: Vec<Vec<f64>>,
+    perm: Vec<usize>,
+}
+
+impl LuFactorization {
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let n = self.lu.len();
+        assert!(b.len() == n);
+
+        let mut x: Vec<f64> = self.perm.iter().map(|idx| b[*idx]).collect();
+        for row in 0..n {
+            let head: f64 = (0..row).map(|col| self.lu[row][col] * x[col]).sum();
+            x[row] -= head;
+        }
+        for row in (0..n).rev() {
+            let tail: f64 = (row + 1..n).map(|col| self.lu[row][col] * x[col]).sum();
+            x[row] = (x[row] - tail) / self.lu[row][row];
+        }
+        x
+    }
+
+    // Solves A^T x = b from the same factors: U^T then L^T, then undo the row permutation
+    pub fn solve_transpose(&self, b: &[f64]) -> Vec<f64> {
+        let n = self.lu.len();
+        assert!(b.len() == n);
+
+        let mut w = b.to_vec();
+        for row in 0..n {
+            let head: f64 = (0..row).map(|col| self.lu[col][row] * w[col]).sum();
+            w[row] = (w[row] - head) / self.lu[row][row];
+        }
+        for row in (0..n).rev() {
+            let tail: f64 = (row + 1..n).map(|col| self.lu[col][row] * w[col]).sum();
+            w[row] -= tail;
+        }
+        let mut x = vec![0.0; n];
+        for (idx, val) in std::iter::zip(&self.perm, w) {
+            x[*idx] = val;
+        }
+        x
+    }
+}
+
+// Outcome of an iterative solve; final_residual is ||b - Ax|| at the returned x
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolveReport {
+    pub iterations: usize,
+    pub final_residual: f64,
+    pub converged: bool,
+}
+
+// M = diag(A), applied as M^-1 r
+pub struct JacobiPreconditioner {
+    inverse_diagonal: Vec<f64>,
+}
+
+impl JacobiPreconditioner {
+    // None if any diagonal entry is zero (or not finite), since M would not be invertible
+    pub fn new(matrix: &SparseMatrix) -> Option<JacobiPreconditioner> {
+        let diagonal = matrix.diagonal();
+        if diagonal.iter().any(|d| *d == 0.0 || !d.is_finite()) {
+            return None;
+        }
+        Some(JacobiPreconditioner {
+            inverse_diagonal: diagonal.iter().map(|d| 1.0 / d).collect(),
+        })
+    }
+
+    pub fn apply(&self, r: &[f64]) -> Vec<f64> {
+        assert!(r.len() == self.inverse_diagonal.len());
+        std::iter::zip(r, &self.inverse_diagonal)
+            .map(|(ri, inv)| ri * inv)
+            .collect()
+    }
+}
+
+// Borrowed A^T that answers queries by swapping indices instead of materializing
+pub struct TransposeView<'a> {
+    matrix: &'a SparseMatrix,
+}
+
+impl TransposeView<'_> {
+    pub fn shape(&self) -> (u64, u64) {
+        (self.matrix.shape.1, self.matrix.shape.0)
+    }
+
+    pub fn peek_at(&self, row: u64, col: u64) -> Option<f64> {
+        self.matrix.peek_at(col, row)
+    }
+
+    pub fn matvec(&self, x: &[f64]) -> Vec<f64> {
+        self.matrix.matvec_transpose(x)
+    }
+}
+
 pub struct RowIterator<'a> {
     matrix: &'a SparseMatrix,
+    // CSR arrays built once up front when the matrix's cached ones are stale
+    stale_compressed: Option<(Vec<u64>, Vec<u64>, Vec<f64>)>,
     row_iter_idx: usize,
+    row_iter_end: usize,
 }
 
 impl Iterator for RowIterator<'_> {
@@ -27,17 +140,20 @@ impl Iterator for RowIterator<'_> {
     type Item = Vec<f64>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Check for compressed updates here?
-        if self.row_iter_idx < self.matrix.shape.0 as usize {
-            let start = self.matrix.compressed_rowarray[self.row_iter_idx] as usize;
-            let end = self.matrix.compressed_rowarray[self.row_iter_idx + 1] as usize;
-
-            let colslice = &self.matrix.compressed_colarray[start..end];
-            let dataslice = &self.matrix.compressed_dataarray[start..end];
+        if self.row_iter_idx < self.row_iter_end {
+            let (rowarray, colarray, dataarray) = match &self.stale_compressed {
+                Some((rowarray, colarray, dataarray)) => (rowarray, colarray, dataarray),
+                None => (
+                    &self.matrix.compressed_rowarray,
+                    &self.matrix.compressed_colarray,
+                    &self.matrix.compressed_dataarray,
+                ),
+            };
+            let start = rowarray[self.row_iter_idx] as usize;
+            let end = rowarray[self.row_iter_idx + 1] as usize;
 
             let mut retvec: Vec<f64> = vec![0.0; self.matrix.shape.1 as usize];
-
-            for (col, val) in std::iter::zip(colslice, dataslice) {
+            for (col, val) in std::iter::zip(&colarray[start..end], &dataarray[start..end]) {
                 retvec[*col as usize] = *val;
             }
 
@@ -50,17 +166,158 @@ impl Iterator for RowIterator<'_> {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum SparseError {
+    DimensionMismatch {
+        expected: u64,
+        found: u64,
+    },
+    IndexOutOfBounds {
+        row: u64,
+        col: u64,
+        shape: (u64, u64),
+    },
+    NonFinite {
+        row: u64,
+        col: u64,
+    },
+    Io(String),
+    Parse {
+        line: usize,
+        message: String,
+    },
+    TooManyNonzeros {
+        estimate: u64,
+        limit: u64,
+    },
+}
+
+impl From<std::io::Error> for SparseError {
+    fn from(err: std::io::Error) -> Self {
+        SparseError::Io(err.to_string())
+    }
+}
+
+impl std::fmt::Display for SparseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SparseError::DimensionMismatch { expected, found } => {
+                write!(
+                    f,
+                    "dimension mismatch: expected {}, found {}",
+                    expected, found
+                )
+            }
+            SparseError::IndexOutOfBounds { row, col, shape } => write!(
+                f,
+                "index ({}, {}) out of bounds for shape ({}, {})",
+                row, col, shape.0, shape.1
+            ),
+            SparseError::NonFinite { row, col } => {
+                write!(f, "non-finite value stored at ({}, {})", row, col)
+            }
+            SparseError::Io(message) => write!(f, "io error: {}", message),
+            SparseError::Parse { line, message } => {
+                write!(f, "parse error on line {}: {}", line, message)
+            }
+            SparseError::TooManyNonzeros { estimate, limit } => write!(
+                f,
+                "operation would produce {} nonzeros, above the limit of {}",
+                estimate, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SparseError {}
+
+fn _check_dimension(expected: u64, found: u64) -> Result<(), SparseError> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(SparseError::DimensionMismatch { expected, found })
+    }
+}
+
+// Advisory storage choice from recommended_format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageHint {
+    Dense,
+    Csr,
+    Coo,
+}
+
+// How to_undirected merges the weights of edges present in both directions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeCombine {
+    Max,
+    Sum,
+}
+
+// Index convention of external triplet data; storage is always 0-based
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexBase {
+    Zero,
+    One,
+}
+
+impl IndexBase {
+    fn offset(self) -> u64 {
+        match self {
+            IndexBase::Zero => 0,
+            IndexBase::One => 1,
+        }
+    }
+}
+
+// Small seeded PRNG (SplitMix64) so generators stay reproducible without extra deps
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Uniform in [0, bound)
+    fn next_below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+fn _dot(a: &[f64], b: &[f64]) -> f64 {
+    std::iter::zip(a, b).map(|(x, y)| x * y).sum()
+}
+
+fn _norm2(a: &[f64]) -> f64 {
+    _dot(a, a).sqrt()
+}
+
 impl Default for SparseMatrix {
     fn default() -> Self {
-        Self::new()
+        Self::empty()
     }
 }
 
 impl SparseMatrix {
-    fn _update_compressed(&mut self) {
-        self.compressed_rowarray.clear();
-        self.compressed_colarray.clear();
-        self.compressed_dataarray.clear();
+    fn _build_compressed(&self) -> (Vec<u64>, Vec<u64>, Vec<f64>) {
+        let mut rowarray: Vec<u64> = vec![];
+        let mut colarray: Vec<u64> = vec![];
+        let mut dataarray: Vec<f64> = vec![];
 
         // Create row vecs that we'll sort by col
         let mut row_vecs: Vec<Vec<(u64, f64)>> = vec![];
@@ -68,35 +325,231 @@ impl SparseMatrix {
             row_vecs.push(vec![]);
         }
 
+        // Explicit zeros stay in the map (if kept) but never reach the compressed form
         for ((row, col), val) in self.values.iter() {
-            row_vecs[*row as usize].push((*col, *val));
+            if *val != 0.0 {
+                row_vecs[*row as usize].push((*col, *val));
+            }
         }
+        // HashMap iteration order is arbitrary, but columns are unique within a row so this
+        // sort fully determines the output. sort_by_key is stable, so a future variant that
+        // allows repeated columns would keep its own insertion order rather than map order.
         for rowidx in 0..self.shape.0 {
             row_vecs[rowidx as usize].sort_by_key(|a| a.0);
         }
 
-        self.compressed_rowarray.push(0);
+        rowarray.push(0);
         for row in row_vecs {
             for (col, val) in row {
-                self.compressed_colarray.push(col);
-                self.compressed_dataarray.push(val);
+                colarray.push(col);
+                dataarray.push(val);
+            }
+            rowarray.push(dataarray.len() as u64);
+        }
+
+        (rowarray, colarray, dataarray)
+    }
+
+    fn _update_compressed(&mut self) {
+        let (rowarray, colarray, dataarray) = self._build_compressed();
+        self.compressed_rowarray = rowarray;
+        self.compressed_colarray = colarray;
+        self.compressed_dataarray = dataarray;
+
+        self.compressed_updated = true;
+        self.rows_tracked = false;
+        self.dirty_rows.clear();
+        self._update_band();
+    }
+
+    // Re-sorts only the dirty rows and copies clean row segments over from the old CSR
+    // arrays in one pass. Falls back to a full rebuild if any edit went untracked, or once
+    // more than a quarter of the rows are dirty and the re-sort saves little.
+    fn _update_compressed_incremental(&mut self) {
+        if !self.rows_tracked || self.dirty_rows.len() as u64 * 4 > self.shape.0 {
+            self._update_compressed();
+            return;
+        }
+
+        let mut row_vecs: HashMap<u64, Vec<(u64, f64)>> =
+            self.dirty_rows.iter().map(|row| (*row, vec![])).collect();
+        for ((row, col), val) in self.values.iter() {
+            if *val != 0.0 {
+                if let Some(entries) = row_vecs.get_mut(row) {
+                    entries.push((*col, *val));
+                }
+            }
+        }
+
+        let old_rowarray = std::mem::take(&mut self.compressed_rowarray);
+        let old_colarray = std::mem::take(&mut self.compressed_colarray);
+        let old_dataarray = std::mem::take(&mut self.compressed_dataarray);
+        let mut rowarray = Vec::with_capacity(old_rowarray.len());
+        let mut colarray = Vec::with_capacity(old_colarray.len());
+        let mut dataarray = Vec::with_capacity(old_dataarray.len());
+
+        rowarray.push(0);
+        for row in 0..self.shape.0 {
+            match row_vecs.get_mut(&row) {
+                Some(entries) => {
+                    entries.sort_by_key(|a| a.0);
+                    for (col, val) in entries.iter() {
+                        colarray.push(*col);
+                        dataarray.push(*val);
+                    }
+                }
+                None => {
+                    let start = old_rowarray[row as usize] as usize;
+                    let end = old_rowarray[row as usize + 1] as usize;
+                    colarray.extend_from_slice(&old_colarray[start..end]);
+                    dataarray.extend_from_slice(&old_dataarray[start..end]);
+                }
+            }
+            rowarray.push(dataarray.len() as u64);
+        }
+        self.compressed_rowarray = rowarray;
+        self.compressed_colarray = colarray;
+        self.compressed_dataarray = dataarray;
+
+        self.compressed_updated = true;
+        self.rows_tracked = false;
+        self.dirty_rows.clear();
+        self._update_band();
+    }
+
+    // Only kept for square matrices whose band is under a quarter of the columns wide
+    // and at least half filled, so it never costs much more memory than the CSR arrays
+    fn _update_band(&mut self) {
+        self.band = None;
+        let n = self.shape.0 as usize;
+        if self.shape.0 != self.shape.1 || n == 0 {
+            return;
+        }
+        let rowarray = &self.compressed_rowarray;
+        let colarray = &self.compressed_colarray;
+
+        let mut half = 0;
+        for row in 0..n {
+            for col in &colarray[rowarray[row] as usize..rowarray[row + 1] as usize] {
+                half = half.max(row.abs_diff(*col as usize));
+            }
+        }
+        let width = 2 * half + 1;
+        if width * 4 > n || n * width > 2 * colarray.len() {
+            return;
+        }
+
+        let mut band = vec![0.0; n * width];
+        for row in 0..n {
+            let start = rowarray[row] as usize;
+            let end = rowarray[row + 1] as usize;
+            for (col, val) in std::iter::zip(
+                &colarray[start..end],
+                &self.compressed_dataarray[start..end],
+            ) {
+                band[(*col as usize + half - row) * n + row] = *val;
             }
-            self.compressed_rowarray
-                .push(self.compressed_dataarray.len() as u64);
         }
+        self.band = Some((half, band));
+    }
+
+    // A single-row edit: remember the row if the cache was current before it
+    fn _mark_row_dirty(&mut self, row: u64) {
+        if self.compressed_updated {
+            self.compressed_updated = false;
+            self.rows_tracked = true;
+            self.band = None;
+        }
+        if self.rows_tracked {
+            self.dirty_rows.insert(row);
+        }
+    }
+
+    // Any edit that isn't row-tracked forces the next compress to rebuild everything
+    fn _invalidate_compressed(&mut self) {
+        self.compressed_updated = false;
+        self.band = None;
+        self.rows_tracked = false;
+        self.dirty_rows.clear();
+    }
 
-        self.compressed_updated = true
+    // Run f over the CSR arrays, building a temporary copy if the cached ones are stale
+    fn _with_compressed<T>(&self, f: impl FnOnce(&[u64], &[u64], &[f64]) -> T) -> T {
+        if self.compressed_updated {
+            f(
+                &self.compressed_rowarray,
+                &self.compressed_colarray,
+                &self.compressed_dataarray,
+            )
+        } else {
+            let (rowarray, colarray, dataarray) = self._build_compressed();
+            f(&rowarray, &colarray, &dataarray)
+        }
     }
 
+    // Builds the CSR arrays as stored; NaN/inf are not checked for here, see try_compress
     pub fn explicitly_compress(&mut self) {
+        self._update_compressed_incremental();
+    }
+
+    // Refuses to build CSR arrays containing NaN/inf, which solvers would silently spread
+    #[allow(dead_code)]
+    pub fn try_compress(&mut self) -> Result<(), SparseError> {
+        if let Some(((row, col), _)) = self.sorted_entries().iter().find(|(_, v)| !v.is_finite()) {
+            return Err(SparseError::NonFinite {
+                row: *row,
+                col: *col,
+            });
+        }
         self._update_compressed();
+        Ok(())
     }
 
     #[allow(dead_code)]
     pub fn row_iter(&self) -> RowIterator<'_> {
+        self._row_iterator(0, self.shape.0 as usize)
+    }
+
+    #[allow(dead_code)]
+    pub fn row_iter_range(&self, rows: Range<u64>) -> RowIterator<'_> {
+        assert!(rows.start <= rows.end && rows.end <= self.shape.0);
+        self._row_iterator(rows.start as usize, rows.end as usize)
+    }
+
+    // Empty ranges (including every range of a zero-row matrix) never touch the arrays
+    fn _row_iterator(&self, start: usize, end: usize) -> RowIterator<'_> {
+        let stale_compressed =
+            (!self.compressed_updated && start < end).then(|| self._build_compressed());
         RowIterator {
             matrix: self,
-            row_iter_idx: 0,
+            stale_compressed,
+            row_iter_idx: start,
+            row_iter_end: end,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn write_row_into(&self, row: u64, buf: &mut [f64]) {
+        assert!(row < self.shape.0);
+        assert!(buf.len() as u64 == self.shape.1);
+
+        buf.fill(0.0);
+        if self.compressed_updated {
+            let start = self.compressed_rowarray[row as usize] as usize;
+            let end = self.compressed_rowarray[row as usize + 1] as usize;
+            for (col, val) in std::iter::zip(
+                &self.compressed_colarray[start..end],
+                &self.compressed_dataarray[start..end],
+            ) {
+                buf[*col as usize] = *val;
+            }
+        } else {
+            // Stale CSR: scan the map rather than rebuilding everything for one row
+            for ((entry_row, col), val) in self.values.iter() {
+                if *entry_row == row {
+                    buf[*col as usize] = *val;
+                }
+            }
         }
     }
 
@@ -105,7 +558,11 @@ impl SparseMatrix {
         SparseMatrix {
             shape: (0, 0),
             values: HashMap::new(),
+            keep_explicit_zeros: false,
             compressed_updated: false,
+            dirty_rows: HashSet::new(),
+            band: None,
+            rows_tracked: false,
             compressed_rowarray: vec![],
             compressed_colarray: vec![],
             compressed_dataarray: vec![],
@@ -113,6 +570,12 @@ impl SparseMatrix {
         }
     }
 
+    // The canonical 0x0 matrix; same as new()
+    #[allow(dead_code)]
+    pub fn empty() -> SparseMatrix {
+        SparseMatrix::new()
+    }
+
     #[allow(dead_code)]
     pub fn empty_with_shape(n: u64, m: u64) -> SparseMatrix {
         let mut value_map = HashMap::new();
@@ -121,7 +584,11 @@ impl SparseMatrix {
         SparseMatrix {
             shape: (n, m),
             values: value_map,
+            keep_explicit_zeros: false,
             compressed_updated: false,
+            dirty_rows: HashSet::new(),
+            band: None,
+            rows_tracked: false,
             compressed_rowarray: vec![],
             compressed_colarray: vec![],
             compressed_dataarray: vec![],
@@ -129,6 +596,311 @@ impl SparseMatrix {
         }
     }
 
+    // Adopts the map (no per-entry re-insert), after checking it fits the shape; 0.0 values
+    // are dropped since the new matrix doesn't keep explicit zeros
+    #[allow(dead_code)]
+    pub fn from_map(
+        shape: (u64, u64),
+        values: HashMap<(u64, u64), f64>,
+    ) -> Result<SparseMatrix, SparseError> {
+        let mut local = SparseMatrix::new();
+        local.shape = shape;
+        local.values = values;
+        local.validate()?;
+        local.values.retain(|_, val| *val != 0.0);
+        local._invalidate_compressed();
+        Ok(local)
+    }
+
+    #[allow(dead_code)]
+    pub fn from_triplets(
+        shape: (u64, u64),
+        triplets: Vec<(u64, u64, f64)>,
+        base: IndexBase,
+    ) -> SparseMatrix {
+        let offset = base.offset();
+        let mut local = SparseMatrix::empty_with_shape(shape.0, shape.1);
+        for (row, col, val) in triplets {
+            assert!(row >= offset);
+            assert!(col >= offset);
+            local.insert(row - offset, col - offset, val);
+        }
+        local
+    }
+
+    // Coordinate-format Matrix Market (real, integer or pattern; general, symmetric or
+    // skew-symmetric). Indices in the file are 1-based as the format requires.
+    #[allow(dead_code)]
+    pub fn read_matrix_market_from<R: BufRead>(reader: R) -> Result<SparseMatrix, SparseError> {
+        let parse_error = |line: usize, message: &str| SparseError::Parse {
+            line,
+            message: message.to_string(),
+        };
+
+        let mut lines = reader
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line));
+        let (_, header) = lines
+            .next()
+            .ok_or_else(|| parse_error(1, "missing header"))?;
+        let header = header?.to_lowercase();
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        if fields.len() != 5 || fields[0] != "%%matrixmarket" || fields[1] != "matrix" {
+            return Err(parse_error(1, "expected %%MatrixMarket matrix header"));
+        }
+        if fields[2] != "coordinate" {
+            return Err(parse_error(1, "only coordinate format is supported"));
+        }
+        let pattern = match fields[3] {
+            "real" | "integer" => false,
+            "pattern" => true,
+            _ => return Err(parse_error(1, "unsupported field type")),
+        };
+        let mirror_sign = match fields[4] {
+            "general" => None,
+            "symmetric" => Some(1.0),
+            "skew-symmetric" => Some(-1.0),
+            _ => return Err(parse_error(1, "unsupported symmetry")),
+        };
+
+        let mut local: Option<SparseMatrix> = None;
+        let mut expected_entries = 0;
+        let mut entries = 0;
+        // End-of-input errors point at the last line actually read
+        let mut last_line = 1;
+        for (line_no, line) in lines {
+            last_line = line_no;
+            let line = line?;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('%') {
+                continue;
+            }
+            let tokens: Vec<&str> = trimmed.split_whitespace().collect();
+
+            let matrix = match local.as_mut() {
+                Some(matrix) => matrix,
+                None => {
+                    let sizes: Vec<u64> = tokens
+                        .iter()
+                        .map(|t| t.parse::<u64>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| parse_error(line_no, "invalid size line"))?;
+                    if sizes.len() != 3 {
+                        return Err(parse_error(line_no, "size line needs rows, cols, nnz"));
+                    }
+                    expected_entries = sizes[2];
+                    local = Some(SparseMatrix::empty_with_shape(sizes[0], sizes[1]));
+                    continue;
+                }
+            };
+
+            if tokens.len() != if pattern { 2 } else { 3 } {
+                return Err(parse_error(line_no, "wrong number of fields in entry"));
+            }
+            let row: u64 = tokens[0]
+                .parse()
+                .map_err(|_| parse_error(line_no, "invalid row index"))?;
+            let col: u64 = tokens[1]
+                .parse()
+                .map_err(|_| parse_error(line_no, "invalid column index"))?;
+            let val: f64 = if pattern {
+                1.0
+            } else {
+                tokens[2]
+                    .parse()
+                    .map_err(|_| parse_error(line_no, "invalid value"))?
+            };
+            if row == 0 || col == 0 || row > matrix.shape.0 || col > matrix.shape.1 {
+                return Err(SparseError::IndexOutOfBounds {
+                    row,
+                    col,
+                    shape: matrix.shape,
+                });
+            }
+
+            matrix.insert(row - 1, col - 1, val);
+            if let Some(sign) = mirror_sign {
+                if row != col {
+                    matrix.insert(col - 1, row - 1, sign * val);
+                }
+            }
+            entries += 1;
+        }
+
+        let matrix = local.ok_or_else(|| parse_error(last_line, "missing size line"))?;
+        if entries != expected_entries {
+            return Err(SparseError::Parse {
+                line: last_line,
+                message: format!("expected {} entries, found {}", expected_entries, entries),
+            });
+        }
+        Ok(matrix)
+    }
+
+    #[allow(dead_code)]
+    pub fn read_matrix_market<P: AsRef<Path>>(path: P) -> Result<SparseMatrix, SparseError> {
+        let file = File::open(path)?;
+        SparseMatrix::read_matrix_market_from(BufReader::new(file))
+    }
+
+    // row,col,value lines indexed per base; a first non-empty line with no numeric field
+    // is treated as a header and skipped, anything else on it must parse as data
+    #[allow(dead_code)]
+    pub fn read_csv_triplets<R: BufRead>(
+        reader: R,
+        shape: (u64, u64),
+        base: IndexBase,
+    ) -> Result<SparseMatrix, SparseError> {
+        let offset = base.offset();
+        let mut local = SparseMatrix::empty_with_shape(shape.0, shape.1);
+        let mut first_content = true;
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line_no = idx + 1;
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = trimmed.split(',').map(|f| f.trim()).collect();
+            if std::mem::take(&mut first_content)
+                && fields.iter().all(|f| f.parse::<f64>().is_err())
+            {
+                continue;
+            }
+            if fields.len() != 3 {
+                return Err(SparseError::Parse {
+                    line: line_no,
+                    message: "expected row,col,value".to_string(),
+                });
+            }
+            let parse_error = |message: &str| SparseError::Parse {
+                line: line_no,
+                message: message.to_string(),
+            };
+            let row: u64 = fields[0]
+                .parse()
+                .map_err(|_| parse_error("invalid row index"))?;
+            let col: u64 = fields[1]
+                .parse()
+                .map_err(|_| parse_error("invalid column index"))?;
+            let val: f64 = fields[2]
+                .parse()
+                .map_err(|_| parse_error("invalid value"))?;
+            // Bounds are reported in the file's own indexing
+            if row < offset || col < offset || row - offset >= shape.0 || col - offset >= shape.1 {
+                return Err(SparseError::IndexOutOfBounds { row, col, shape });
+            }
+            local.insert(row - offset, col - offset, val);
+        }
+        Ok(local)
+    }
+
+    #[allow(dead_code)]
+    pub fn write_csv_triplets<W: Write>(
+        &self,
+        mut writer: W,
+        header: bool,
+        base: IndexBase,
+    ) -> Result<(), SparseError> {
+        let offset = base.offset();
+        if header {
+            writeln!(writer, "row,col,value")?;
+        }
+        for ((row, col), val) in self.sorted_entries() {
+            writeln!(writer, "{},{},{}", row + offset, col + offset, val)?;
+        }
+        Ok(())
+    }
+
+    // Row-major triplets shifted into the requested index convention
+    #[allow(dead_code)]
+    pub fn to_triplets(&self, base: IndexBase) -> Vec<(u64, u64, f64)> {
+        let offset = base.offset();
+        self.sorted_entries()
+            .into_iter()
+            .map(|((row, col), val)| (row + offset, col + offset, val))
+            .collect()
+    }
+
+    // Assemble [[a, b], [c, d]]
+    #[allow(dead_code)]
+    pub fn from_blocks(
+        a: &SparseMatrix,
+        b: &SparseMatrix,
+        c: &SparseMatrix,
+        d: &SparseMatrix,
+    ) -> Result<SparseMatrix, SparseError> {
+        _check_dimension(a.shape.0, b.shape.0)?;
+        _check_dimension(c.shape.0, d.shape.0)?;
+        _check_dimension(a.shape.1, c.shape.1)?;
+        _check_dimension(b.shape.1, d.shape.1)?;
+
+        let (top, left) = a.shape;
+        let mut local = SparseMatrix::empty_with_shape(top + c.shape.0, left + b.shape.1);
+        for (block, row_offset, col_offset) in
+            [(a, 0, 0), (b, 0, left), (c, top, 0), (d, top, left)]
+        {
+            for ((row, col), val) in block.values.iter() {
+                local
+                    .values
+                    .insert((row + row_offset, col + col_offset), *val);
+            }
+        }
+        Ok(local)
+    }
+
+    #[allow(dead_code)]
+    pub fn random(shape: (u64, u64), density: f64, seed: u64) -> SparseMatrix {
+        assert!((0.0..=1.0).contains(&density));
+        let mut local = SparseMatrix::empty_with_shape(shape.0, shape.1);
+        let target = (density * (shape.0 * shape.1) as f64).round() as usize;
+
+        let mut rng = SplitMix64::new(seed);
+        while local.values.len() < target {
+            let row = rng.next_below(shape.0);
+            let col = rng.next_below(shape.1);
+            let val = 2.0 * rng.next_f64() - 1.0;
+            if val != 0.0 {
+                local.values.insert((row, col), val);
+            }
+        }
+        local
+    }
+
+    #[allow(dead_code)]
+    pub fn random_spd(n: u64, density: f64, seed: u64) -> SparseMatrix {
+        let local = SparseMatrix::random((n, n), density, seed);
+        let mut local = &local + &local.create_transpose();
+
+        // Strict diagonal dominance with a positive diagonal makes the symmetric matrix SPD
+        let mut offdiag_sums = vec![0.0; n as usize];
+        for ((row, col), val) in local.values.iter() {
+            if row != col {
+                offdiag_sums[*row as usize] += val.abs();
+            }
+        }
+        for (idx, sum) in offdiag_sums.iter().enumerate() {
+            local.insert(idx as u64, idx as u64, sum + 1.0);
+        }
+        local
+    }
+
+    #[allow(dead_code)]
+    pub fn shape(&self) -> (u64, u64) {
+        self.shape
+    }
+
+    #[allow(dead_code)]
+    pub fn rows(&self) -> u64 {
+        self.shape.0
+    }
+
+    #[allow(dead_code)]
+    pub fn cols(&self) -> u64 {
+        self.shape.1
+    }
+
     #[allow(dead_code)]
     pub fn identity(n: u64) -> SparseMatrix {
         let mut local = SparseMatrix::empty_with_shape(n, n);
@@ -138,6 +910,11 @@ impl SparseMatrix {
         local
     }
 
+    #[allow(dead_code)]
+    pub fn t(&self) -> TransposeView<'_> {
+        TransposeView { matrix: self }
+    }
+
     #[allow(dead_code)]
     pub fn create_transpose(&self) -> SparseMatrix {
         let mut local = SparseMatrix::empty_with_shape(self.shape.1, self.shape.0);
@@ -153,8 +930,8 @@ impl SparseMatrix {
         assert!(row < self.shape.0);
         assert!(col < self.shape.1);
 
-        self.values.insert((row, col), value);
-        self.compressed_updated = false;
+        self._store(row, col, value);
+        self._mark_row_dirty(row);
     }
 
     #[allow(dead_code)]
@@ -163,9 +940,38 @@ impl SparseMatrix {
             assert!(*row < self.shape.0);
             assert!(*col < self.shape.1);
 
-            self.values.insert((*row, *col), *val);
+            self._store(*row, *col, *val);
+            self._mark_row_dirty(*row);
         }
-        self.compressed_updated = false;
+    }
+
+    #[allow(dead_code)]
+    pub fn update_at<F: FnOnce(f64) -> f64>(&mut self, row: u64, col: u64, f: F) {
+        assert!(row < self.shape.0);
+        assert!(col < self.shape.1);
+
+        let current = self.values.get(&(row, col)).copied().unwrap_or(0.0);
+        self._store(row, col, f(current));
+        self._mark_row_dirty(row);
+    }
+
+    // Writing 0.0 clears the entry unless explicit zeros are being kept
+    fn _store(&mut self, row: u64, col: u64, value: f64) {
+        if value == 0.0 && !self.keep_explicit_zeros {
+            self.values.remove(&(row, col));
+        } else {
+            self.values.insert((row, col), value);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn set_keep_explicit_zeros(&mut self, keep: bool) {
+        self.keep_explicit_zeros = keep;
+    }
+
+    #[allow(dead_code)]
+    pub fn keeps_explicit_zeros(&self) -> bool {
+        self.keep_explicit_zeros
     }
 
     #[allow(dead_code)]
@@ -174,10 +980,30 @@ impl SparseMatrix {
         assert!(row < self.shape.0);
         assert!(col < self.shape.1);
 
-        self.compressed_updated = false;
+        self._mark_row_dirty(row);
         self.values.remove(&(row, col))
     }
 
+    // Bounds are checked for the whole batch before anything is removed; returns how many
+    // of the coordinates were actually stored
+    #[allow(dead_code)]
+    pub fn clear_many(&mut self, coords: &[(u64, u64)]) -> u64 {
+        for (row, col) in coords.iter() {
+            assert!(*row < self.shape.0);
+            assert!(*col < self.shape.1);
+        }
+
+        // Only rows that actually lost an entry become dirty, as with clear_at
+        let mut removed = 0;
+        for (row, col) in coords.iter() {
+            if self.values.remove(&(*row, *col)).is_some() {
+                self._mark_row_dirty(*row);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
     #[allow(dead_code)]
     pub fn peek_at(&self, row: u64, col: u64) -> Option<f64> {
         assert!(row < self.shape.0);
@@ -187,12 +1013,1567 @@ impl SparseMatrix {
     }
 
     #[allow(dead_code)]
-    pub fn num_nonzero(&self) -> u64 {
-        self.values.len() as u64
+    pub fn to_dense(&self) -> Vec<Vec<f64>> {
+        let mut dense = vec![vec![0.0; self.shape.1 as usize]; self.shape.0 as usize];
+        for ((row, col), val) in self.values.iter() {
+            dense[*row as usize][*col as usize] = *val;
+        }
+        dense
     }
 
-    #[allow(dead_code)]
-    pub fn transpose_inplace(&mut self) {
+    #[cfg(feature = "nalgebra")]
+    pub fn to_nalgebra(&self) -> nalgebra::DMatrix<f64> {
+        let mut dense = nalgebra::DMatrix::zeros(self.shape.0 as usize, self.shape.1 as usize);
+        for ((row, col), val) in self.values.iter() {
+            dense[(*row as usize, *col as usize)] = *val;
+        }
+        dense
+    }
+
+    #[cfg(feature = "ndarray")]
+    pub fn to_ndarray(&self) -> ndarray::Array2<f64> {
+        let mut dense = ndarray::Array2::zeros((self.shape.0 as usize, self.shape.1 as usize));
+        for ((row, col), val) in self.values.iter() {
+            dense[[*row as usize, *col as usize]] = *val;
+        }
+        dense
+    }
+
+    // Every stored key must fit inside the declared shape
+    #[allow(dead_code)]
+    pub fn validate(&self) -> Result<(), SparseError> {
+        match self
+            .values
+            .keys()
+            .find(|(row, col)| *row >= self.shape.0 || *col >= self.shape.1)
+        {
+            Some((row, col)) => Err(SparseError::IndexOutOfBounds {
+                row: *row,
+                col: *col,
+                shape: self.shape,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // Stored entries as triplets, in no particular order
+    #[allow(dead_code)]
+    pub fn iter_nonzero(&self) -> impl Iterator<Item = (u64, u64, f64)> + '_ {
+        self.values
+            .iter()
+            .map(|((row, col), val)| (*row, *col, *val))
+    }
+
+    #[allow(dead_code)]
+    pub fn sorted_entries(&self) -> Vec<((u64, u64), f64)> {
+        // Row-major view of the DOK storage, without building the compressed arrays
+        let mut entries: Vec<((u64, u64), f64)> =
+            self.values.iter().map(|(k, v)| (*k, *v)).collect();
+        entries.sort_by_key(|a| a.0);
+        entries
+    }
+
+    #[allow(dead_code)]
+    pub fn is_symmetric(&self) -> bool {
+        if self.shape.0 != self.shape.1 {
+            return false;
+        }
+        self.values
+            .iter()
+            .all(|((row, col), val)| self.values.get(&(*col, *row)).copied().unwrap_or(0.0) == *val)
+    }
+
+    // |a_ii| >= sum of |a_ij| over the rest of row i, for every row
+    #[allow(dead_code)]
+    pub fn is_diagonally_dominant(&self) -> bool {
+        if self.shape.0 != self.shape.1 {
+            return false;
+        }
+        let mut diag = vec![0.0; self.shape.0 as usize];
+        let mut offdiag_sums = vec![0.0; self.shape.0 as usize];
+        for ((row, col), val) in self.values.iter() {
+            if row == col {
+                diag[*row as usize] = val.abs();
+            } else {
+                offdiag_sums[*row as usize] += val.abs();
+            }
+        }
+        std::iter::zip(diag, offdiag_sums).all(|(d, sum)| d >= sum)
+    }
+
+    // Smallest nonzero magnitude stored, 0.0 if there is none
+    #[allow(dead_code)]
+    pub fn min_abs(&self) -> f64 {
+        self.values
+            .values()
+            .map(|val| val.abs())
+            .filter(|val| *val != 0.0)
+            .fold(None, |acc: Option<f64>, val| {
+                Some(acc.map_or(val, |a| a.min(val)))
+            })
+            .unwrap_or(0.0)
+    }
+
+    // Counts of nonzero finite magnitudes in log-spaced bins from the smallest to the largest
+    // of them. With a single distinct magnitude everything lands in the first bin; explicit
+    // zeros, NaN and inf are not counted and don't affect the bin edges.
+    #[allow(dead_code)]
+    pub fn magnitude_histogram(&self, bins: usize) -> Vec<u64> {
+        assert!(bins > 0);
+        let mut counts = vec![0; bins];
+        let magnitudes: Vec<f64> = self
+            .values
+            .values()
+            .map(|val| val.abs())
+            .filter(|val| *val != 0.0 && val.is_finite())
+            .collect();
+        if magnitudes.is_empty() {
+            return counts;
+        }
+
+        let low = magnitudes.iter().copied().fold(f64::INFINITY, f64::min);
+        let high = magnitudes.iter().copied().fold(0.0, f64::max);
+        let (log_low, log_span) = (low.ln(), high.ln() - low.ln());
+        for val in magnitudes {
+            let bin = if log_span == 0.0 {
+                0
+            } else {
+                (((val.ln() - log_low) / log_span * bins as f64) as usize).min(bins - 1)
+            };
+            counts[bin] += 1;
+        }
+        counts
+    }
+
+    // Fraction of positions stored, 0.0 for a matrix with no positions
+    #[allow(dead_code)]
+    pub fn density(&self) -> f64 {
+        let positions = self.shape.0 * self.shape.1;
+        if positions == 0 {
+            return 0.0;
+        }
+        self.values.len() as f64 / positions as f64
+    }
+
+    // Dense above half full; COO when there are fewer entries than rows, so the CSR row
+    // pointer array would outweigh the data; CSR otherwise
+    #[allow(dead_code)]
+    pub fn recommended_format(&self) -> StorageHint {
+        if self.density() > 0.5 {
+            StorageHint::Dense
+        } else if (self.values.len() as u64) < self.shape.0 {
+            StorageHint::Coo
+        } else {
+            StorageHint::Csr
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn max_abs(&self) -> f64 {
+        self.values
+            .values()
+            .fold(0.0, |acc: f64, v| acc.max(v.abs()))
+    }
+
+    // True if nothing nonzero is stored; explicit zeros don't count
+    #[allow(dead_code)]
+    pub fn is_zero(&self) -> bool {
+        self.values.values().all(|val| *val == 0.0)
+    }
+
+    #[allow(dead_code)]
+    pub fn count_where<F: Fn(f64) -> bool>(&self, predicate: F) -> u64 {
+        self.values.values().filter(|val| predicate(**val)).count() as u64
+    }
+
+    #[allow(dead_code)]
+    pub fn explicit_zeros(&self) -> u64 {
+        self.values.values().filter(|val| **val == 0.0).count() as u64
+    }
+
+    // Like ==, but a stored 0.0 and an absent entry compare equal
+    #[allow(dead_code)]
+    pub fn eq_ignoring_explicit_zeros(&self, other: &SparseMatrix) -> bool {
+        let covers = |a: &SparseMatrix, b: &SparseMatrix| {
+            a.values
+                .iter()
+                .all(|(key, val)| b.values.get(key).copied().unwrap_or(0.0) == *val)
+        };
+        self.shape == other.shape && covers(self, other) && covers(other, self)
+    }
+
+    // Largest |self - other| over the union of both patterns, an absent entry reading as 0.0.
+    // NaN if any difference is NaN, so a broken result never compares as close.
+    #[allow(dead_code)]
+    pub fn max_abs_diff(&self, other: &SparseMatrix) -> f64 {
+        assert!(self.shape == other.shape);
+        let nan_max = |acc: f64, d: f64| if d.is_nan() || d > acc { d } else { acc };
+        let one_way = |a: &SparseMatrix, b: &SparseMatrix| {
+            a.values.iter().fold(0.0, |acc: f64, (key, val)| {
+                nan_max(acc, (val - b.values.get(key).copied().unwrap_or(0.0)).abs())
+            })
+        };
+        nan_max(one_way(self, other), one_way(other, self))
+    }
+
+    #[allow(dead_code)]
+    pub fn approx_eq(&self, other: &SparseMatrix, tol: f64) -> bool {
+        self.shape == other.shape && self.max_abs_diff(other) <= tol
+    }
+
+    #[allow(dead_code)]
+    pub fn to_coo_string(&self) -> String {
+        let mut out = String::new();
+        for ((row, col), val) in self.sorted_entries() {
+            out.push_str(&format!("({}, {}) = {}\n", row, col, val));
+        }
+        out
+    }
+
+    // Display layout limited to the top-left max_rows x max_cols block, with … marking
+    // the omitted columns and rows
+    #[allow(dead_code)]
+    pub fn display_truncated(&self, max_rows: usize, max_cols: usize) -> String {
+        let shown_rows = (self.shape.0 as usize).min(max_rows);
+        let shown_cols = (self.shape.1 as usize).min(max_cols);
+        let cols_cut = shown_cols < self.shape.1 as usize;
+        let rows_cut = shown_rows < self.shape.0 as usize;
+
+        let mut inner_line_width = 8 * shown_cols;
+        if cols_cut {
+            inner_line_width += 3;
+        }
+        let padding = " ".repeat(inner_line_width);
+
+        let mut out = format!("\t/{}\\\n", padding);
+        for row in 0..shown_rows as u64 {
+            out.push_str("\t| ");
+            for col in 0..shown_cols as u64 {
+                if col != 0 {
+                    out.push_str(", ");
+                }
+                let elem = self.values.get(&(row, col)).copied().unwrap_or(0.0);
+                out.push_str(&format!("{:>6.2}", elem));
+            }
+            if cols_cut {
+                out.push_str(", …");
+            }
+            out.push_str(" |\n");
+        }
+        if rows_cut {
+            out.push_str("\t| …\n");
+        }
+        out.push_str(&format!("\t\\{}/\n", padding));
+        out
+    }
+
+    #[allow(dead_code)]
+    pub fn num_nonzero(&self) -> u64 {
+        self.values.len() as u64
+    }
+
+    #[allow(dead_code)]
+    pub fn out_degree(&self) -> Vec<u64> {
+        let mut degrees = vec![0; self.shape.0 as usize];
+        for (row, _col) in self.values.keys() {
+            degrees[*row as usize] += 1;
+        }
+        degrees
+    }
+
+    #[allow(dead_code)]
+    pub fn in_degree(&self) -> Vec<u64> {
+        let mut degrees = vec![0; self.shape.1 as usize];
+        for (_row, col) in self.values.keys() {
+            degrees[*col as usize] += 1;
+        }
+        degrees
+    }
+
+    #[allow(dead_code)]
+    pub fn isolated_nodes(&self) -> Vec<u64> {
+        let out_degrees = self.out_degree();
+        let in_degrees = self.in_degree();
+        let num_nodes = self.shape.0.max(self.shape.1) as usize;
+
+        // Non-square shapes: indices past one dimension only count the other
+        (0..num_nodes)
+            .filter(|idx| {
+                out_degrees.get(*idx).copied().unwrap_or(0) == 0
+                    && in_degrees.get(*idx).copied().unwrap_or(0) == 0
+            })
+            .map(|idx| idx as u64)
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn reachable_from(&self, start: u64) -> Vec<u64> {
+        assert!(self.shape.0 == self.shape.1);
+        assert!(start < self.shape.0);
+
+        self._with_compressed(|rowarray, colarray, _| {
+            let mut visited = vec![false; self.shape.0 as usize];
+            let mut order: Vec<u64> = vec![];
+            let mut queue = std::collections::VecDeque::new();
+
+            visited[start as usize] = true;
+            queue.push_back(start);
+            while let Some(node) = queue.pop_front() {
+                order.push(node);
+                let begin = rowarray[node as usize] as usize;
+                let end = rowarray[node as usize + 1] as usize;
+                for neighbor in &colarray[begin..end] {
+                    if !visited[*neighbor as usize] {
+                        visited[*neighbor as usize] = true;
+                        queue.push_back(*neighbor);
+                    }
+                }
+            }
+            order
+        })
+    }
+
+    // Diagonal, strictly-lower and strictly-upper parts, with d + l + u == self
+    #[allow(dead_code)]
+    pub fn split_dlu(&self) -> (SparseMatrix, SparseMatrix, SparseMatrix) {
+        let mut d = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        let mut l = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        let mut u = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        for ((row, col), val) in self.values.iter() {
+            let part = match row.cmp(col) {
+                std::cmp::Ordering::Equal => &mut d,
+                std::cmp::Ordering::Greater => &mut l,
+                std::cmp::Ordering::Less => &mut u,
+            };
+            part.values.insert((*row, *col), *val);
+        }
+        (d, l, u)
+    }
+
+    #[allow(dead_code)]
+    pub fn threshold_mask(&self, predicate: impl Fn(f64) -> bool) -> SparseMatrix {
+        let mut local = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        for (key, val) in self.values.iter() {
+            if predicate(*val) {
+                local.values.insert(*key, 1.0);
+            }
+        }
+        local
+    }
+
+    #[allow(dead_code)]
+    pub fn submatrix(&self, rows: Range<u64>, cols: Range<u64>) -> SparseMatrix {
+        assert!(rows.start <= rows.end && rows.end <= self.shape.0);
+        assert!(cols.start <= cols.end && cols.end <= self.shape.1);
+        let mut local =
+            SparseMatrix::empty_with_shape(rows.end - rows.start, cols.end - cols.start);
+        for ((row, col), val) in self.values.iter() {
+            if rows.contains(row) && cols.contains(col) {
+                local
+                    .values
+                    .insert((row - rows.start, col - cols.start), *val);
+            }
+        }
+        local
+    }
+
+    // Every coordinate of the window in row-major order, implicit zeros included
+    #[allow(dead_code)]
+    pub fn dense_window_iter(
+        &self,
+        rows: Range<u64>,
+        cols: Range<u64>,
+    ) -> impl Iterator<Item = (u64, u64, f64)> + '_ {
+        assert!(rows.start <= rows.end && rows.end <= self.shape.0);
+        assert!(cols.start <= cols.end && cols.end <= self.shape.1);
+        rows.flat_map(move |row| {
+            cols.clone().map(move |col| {
+                let val = self.values.get(&(row, col)).copied().unwrap_or(0.0);
+                (row, col, val)
+            })
+        })
+    }
+
+    // Overwrites the block's whole footprint, so positions empty in block are cleared
+    #[allow(dead_code)]
+    pub fn set_submatrix(&mut self, row_offset: u64, col_offset: u64, block: &SparseMatrix) {
+        assert!(row_offset + block.shape.0 <= self.shape.0);
+        assert!(col_offset + block.shape.1 <= self.shape.1);
+
+        let rows = row_offset..row_offset + block.shape.0;
+        let cols = col_offset..col_offset + block.shape.1;
+        self.values
+            .retain(|(row, col), _| !(rows.contains(row) && cols.contains(col)));
+        for ((row, col), val) in block.values.iter() {
+            self.values
+                .insert((row + row_offset, col + col_offset), *val);
+        }
+        self._invalidate_compressed();
+    }
+
+    #[allow(dead_code)]
+    pub fn bandwidth(&self) -> u64 {
+        self.values
+            .keys()
+            .map(|(row, col)| row.abs_diff(*col))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn _assert_permutation(perm: &[u64], n: u64) {
+        assert!(perm.len() as u64 == n);
+        let mut seen = vec![false; n as usize];
+        for idx in perm {
+            assert!(*idx < n);
+            assert!(!seen[*idx as usize]);
+            seen[*idx as usize] = true;
+        }
+    }
+
+    // New row i is old row perm[i]
+    #[allow(dead_code)]
+    pub fn permute_rows(&self, perm: &[u64]) -> SparseMatrix {
+        SparseMatrix::_assert_permutation(perm, self.shape.0);
+        let mut inverse = vec![0; perm.len()];
+        for (new_idx, old_idx) in perm.iter().enumerate() {
+            inverse[*old_idx as usize] = new_idx as u64;
+        }
+
+        let mut local = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        for ((row, col), val) in self.values.iter() {
+            local.insert(inverse[*row as usize], *col, *val);
+        }
+        local
+    }
+
+    // New col j is old col perm[j]
+    #[allow(dead_code)]
+    pub fn permute_cols(&self, perm: &[u64]) -> SparseMatrix {
+        SparseMatrix::_assert_permutation(perm, self.shape.1);
+        let mut inverse = vec![0; perm.len()];
+        for (new_idx, old_idx) in perm.iter().enumerate() {
+            inverse[*old_idx as usize] = new_idx as u64;
+        }
+
+        let mut local = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        for ((row, col), val) in self.values.iter() {
+            local.insert(*row, inverse[*col as usize], *val);
+        }
+        local
+    }
+
+    // P A P^T: entry (i, j) of the result is entry (perm[i], perm[j]) of self
+    #[allow(dead_code)]
+    pub fn permute_symmetric(&self, perm: &[u64]) -> SparseMatrix {
+        assert!(self.shape.0 == self.shape.1);
+        SparseMatrix::_assert_permutation(perm, self.shape.0);
+        let mut inverse = vec![0; perm.len()];
+        for (new_idx, old_idx) in perm.iter().enumerate() {
+            inverse[*old_idx as usize] = new_idx as u64;
+        }
+
+        let mut local = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        for ((row, col), val) in self.values.iter() {
+            local
+                .values
+                .insert((inverse[*row as usize], inverse[*col as usize]), *val);
+        }
+        local
+    }
+
+    // Neighbor lists of the symmetrized pattern (A + A^T), without self loops
+    fn _symmetric_adjacency(&self) -> Vec<Vec<u64>> {
+        assert!(self.shape.0 == self.shape.1);
+        let mut adjacency: Vec<Vec<u64>> = vec![vec![]; self.shape.0 as usize];
+        for (row, col) in self.values.keys() {
+            if row != col {
+                adjacency[*row as usize].push(*col);
+                adjacency[*col as usize].push(*row);
+            }
+        }
+        for neighbors in adjacency.iter_mut() {
+            neighbors.sort();
+            neighbors.dedup();
+        }
+        adjacency
+    }
+
+    // Symmetric adjacency with an edge wherever self has one in either direction. Self
+    // loops are a single edge and are copied unchanged.
+    #[allow(dead_code)]
+    pub fn to_undirected(&self, combine: EdgeCombine) -> SparseMatrix {
+        assert!(self.shape.0 == self.shape.1);
+        let mut local = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        for ((row, col), val) in self.values.iter() {
+            if row == col {
+                local.values.insert((*row, *col), *val);
+                continue;
+            }
+            let weight = match self.values.get(&(*col, *row)) {
+                None => *val,
+                Some(reverse) => match combine {
+                    EdgeCombine::Max => val.max(*reverse),
+                    EdgeCombine::Sum => val + reverse,
+                },
+            };
+            if weight != 0.0 {
+                local.values.insert((*row, *col), weight);
+                local.values.insert((*col, *row), weight);
+            }
+        }
+        local
+    }
+
+    // Component label per node over the undirected pattern, numbered by lowest member
+    #[allow(dead_code)]
+    pub fn connected_components(&self) -> Vec<u64> {
+        let adjacency = self._symmetric_adjacency();
+        let mut labels: Vec<Option<u64>> = vec![None; adjacency.len()];
+        let mut next_label = 0;
+
+        for root in 0..adjacency.len() {
+            if labels[root].is_some() {
+                continue;
+            }
+            labels[root] = Some(next_label);
+            let mut stack = vec![root as u64];
+            while let Some(node) = stack.pop() {
+                for neighbor in &adjacency[node as usize] {
+                    if labels[*neighbor as usize].is_none() {
+                        labels[*neighbor as usize] = Some(next_label);
+                        stack.push(*neighbor);
+                    }
+                }
+            }
+            next_label += 1;
+        }
+        labels.into_iter().map(|label| label.unwrap()).collect()
+    }
+
+    // Greedy minimum degree on the elimination graph of the symmetric pattern: repeatedly
+    // eliminate the node with the fewest neighbors (lowest index on ties), joining its
+    // neighbors into a clique to model the fill that elimination creates
+    #[allow(dead_code)]
+    pub fn min_degree_permutation(&self) -> Vec<u64> {
+        let mut adjacency: Vec<std::collections::BTreeSet<u64>> = self
+            ._symmetric_adjacency()
+            .into_iter()
+            .map(|neighbors| neighbors.into_iter().collect())
+            .collect();
+        let n = adjacency.len();
+        let mut eliminated = vec![false; n];
+        let mut order: Vec<u64> = Vec::with_capacity(n);
+
+        for _ in 0..n {
+            let pivot = (0..n)
+                .filter(|node| !eliminated[*node])
+                .min_by_key(|node| adjacency[*node].len())
+                .unwrap();
+            eliminated[pivot] = true;
+            order.push(pivot as u64);
+
+            let neighbors: Vec<u64> = std::mem::take(&mut adjacency[pivot]).into_iter().collect();
+            for node in neighbors.iter() {
+                let entry = &mut adjacency[*node as usize];
+                entry.remove(&(pivot as u64));
+                entry.extend(neighbors.iter().filter(|other| *other != node));
+            }
+        }
+        order
+    }
+
+    // BFS level sets from root, restricted to nodes not yet placed
+    fn _level_structure(adjacency: &[Vec<u64>], root: u64, placed: &[bool]) -> Vec<Vec<u64>> {
+        let mut seen = placed.to_vec();
+        let mut levels: Vec<Vec<u64>> = vec![vec![root]];
+        seen[root as usize] = true;
+        loop {
+            let mut next: Vec<u64> = vec![];
+            for node in levels.last().unwrap() {
+                for neighbor in &adjacency[*node as usize] {
+                    if !seen[*neighbor as usize] {
+                        seen[*neighbor as usize] = true;
+                        next.push(*neighbor);
+                    }
+                }
+            }
+            if next.is_empty() {
+                return levels;
+            }
+            levels.push(next);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn rcm_permutation(&self) -> Vec<u64> {
+        let adjacency = self._symmetric_adjacency();
+        let n = adjacency.len();
+        let degree = |node: &u64| adjacency[*node as usize].len();
+
+        let mut placed = vec![false; n];
+        let mut order: Vec<u64> = Vec::with_capacity(n);
+        while order.len() < n {
+            // Each component starts from a pseudo-peripheral node (George-Liu)
+            let mut root = (0..n as u64)
+                .filter(|node| !placed[*node as usize])
+                .min_by_key(degree)
+                .unwrap();
+            let mut levels = SparseMatrix::_level_structure(&adjacency, root, &placed);
+            loop {
+                let candidate = *levels
+                    .last()
+                    .unwrap()
+                    .iter()
+                    .min_by_key(|node| degree(node))
+                    .unwrap();
+                let candidate_levels =
+                    SparseMatrix::_level_structure(&adjacency, candidate, &placed);
+                if candidate_levels.len() <= levels.len() {
+                    break;
+                }
+                root = candidate;
+                levels = candidate_levels;
+            }
+
+            // Cuthill-McKee: BFS visiting unplaced neighbors by increasing degree
+            let component_start = order.len();
+            placed[root as usize] = true;
+            order.push(root);
+            let mut head = component_start;
+            while head < order.len() {
+                let node = order[head];
+                head += 1;
+                let mut neighbors: Vec<u64> = adjacency[node as usize]
+                    .iter()
+                    .filter(|neighbor| !placed[**neighbor as usize])
+                    .copied()
+                    .collect();
+                neighbors.sort_by_key(|neighbor| (degree(neighbor), *neighbor));
+                for neighbor in neighbors {
+                    placed[neighbor as usize] = true;
+                    order.push(neighbor);
+                }
+            }
+        }
+
+        order.reverse();
+        order
+    }
+
+    #[allow(dead_code)]
+    pub fn has_non_finite(&self) -> bool {
+        self.values.values().any(|val| !val.is_finite())
+    }
+
+    #[allow(dead_code)]
+    pub fn replace_non_finite(&mut self, with: f64) {
+        for val in self.values.values_mut() {
+            if !val.is_finite() {
+                *val = with;
+            }
+        }
+        if with == 0.0 {
+            self.values.retain(|_, val| *val != 0.0);
+        }
+        self._invalidate_compressed();
+    }
+
+    #[allow(dead_code)]
+    pub fn scale(&mut self, factor: f64) {
+        if factor == 0.0 {
+            self.values.clear();
+        } else {
+            for val in self.values.values_mut() {
+                *val *= factor;
+            }
+        }
+        self._invalidate_compressed();
+    }
+
+    // Drop every entry with |value| <= tol
+    #[allow(dead_code)]
+    pub fn prune(&mut self, tol: f64) {
+        self.values.retain(|_, val| val.abs() > tol);
+        self._invalidate_compressed();
+    }
+
+    #[allow(dead_code)]
+    pub fn with_scaled(&self, factor: f64) -> SparseMatrix {
+        let mut local = self.clone();
+        local.scale(factor);
+        local
+    }
+
+    #[allow(dead_code)]
+    pub fn with_pruned(&self, tol: f64) -> SparseMatrix {
+        let mut local = self.clone();
+        local.prune(tol);
+        local
+    }
+
+    #[allow(dead_code)]
+    pub fn with_transpose(&self) -> SparseMatrix {
+        let mut local = self.clone();
+        local.transpose_inplace();
+        local
+    }
+
+    // Drop stored exact zeros and rebuild the compressed arrays
+    #[allow(dead_code)]
+    pub fn coalesce(&mut self) {
+        self.values.retain(|_, val| *val != 0.0);
+        self._update_compressed();
+    }
+
+    // Scale into [-1, 1] by the largest magnitude; an all-zero matrix is left alone
+    #[allow(dead_code)]
+    pub fn normalize(&mut self) {
+        let max_abs = self.max_abs();
+        if max_abs != 0.0 {
+            self.scale(1.0 / max_abs);
+        }
+    }
+
+    // Returns the applied factor, or None (leaving self untouched) if the trace is zero
+    #[allow(dead_code)]
+    pub fn scale_to_trace(&mut self, target: f64) -> Option<f64> {
+        let trace = self.trace();
+        if trace == 0.0 {
+            return None;
+        }
+        let factor = target / trace;
+        self.scale(factor);
+        Some(factor)
+    }
+
+    #[allow(dead_code)]
+    pub fn normalize_rows_l2(&mut self) {
+        let mut sq_norms = vec![0.0; self.shape.0 as usize];
+        for ((row, _col), val) in self.values.iter() {
+            sq_norms[*row as usize] += val * val;
+        }
+        for ((row, _col), val) in self.values.iter_mut() {
+            let norm = sq_norms[*row as usize].sqrt();
+            if norm != 0.0 {
+                *val /= norm;
+            }
+        }
+        self._invalidate_compressed();
+    }
+
+    // Copy with every column scaled to sum to 1, the PageRank transition convention. Columns
+    // summing to zero are left alone, or, with uniform_dangling, replaced by 1/rows throughout
+    #[allow(dead_code)]
+    pub fn to_column_stochastic(&self, uniform_dangling: bool) -> SparseMatrix {
+        let mut col_sums = vec![0.0; self.shape.1 as usize];
+        for ((_row, col), val) in self.values.iter() {
+            col_sums[*col as usize] += val;
+        }
+
+        let mut local = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        for ((row, col), val) in self.values.iter() {
+            let sum = col_sums[*col as usize];
+            if sum != 0.0 {
+                local.values.insert((*row, *col), val / sum);
+            } else if !uniform_dangling {
+                local.values.insert((*row, *col), *val);
+            }
+        }
+        if uniform_dangling && self.shape.0 > 0 {
+            let fill = 1.0 / self.shape.0 as f64;
+            for (col, sum) in col_sums.iter().enumerate() {
+                if *sum == 0.0 {
+                    for row in 0..self.shape.0 {
+                        local.values.insert((row, col as u64), fill);
+                    }
+                }
+            }
+        }
+        local
+    }
+
+    // Values too large to scale by 10^decimals without overflow are left as they are;
+    // at that magnitude they have no digits that far past the point anyway
+    #[allow(dead_code)]
+    pub fn round_to(&mut self, decimals: u32) {
+        let factor = 10f64.powi(decimals.min(i32::MAX as u32) as i32);
+        for val in self.values.values_mut() {
+            let scaled = *val * factor;
+            if factor.is_finite() && scaled.is_finite() {
+                *val = scaled.round() / factor;
+            }
+        }
+        self.values.retain(|_, val| *val != 0.0);
+        self._invalidate_compressed();
+    }
+
+    // self += alpha * u v^T, touching only positions where both u[i] and v[j] are nonzero
+    #[allow(dead_code)]
+    pub fn rank1_update(&mut self, alpha: f64, u: &[f64], v: &[f64]) {
+        assert!(u.len() as u64 == self.shape.0);
+        assert!(v.len() as u64 == self.shape.1);
+        for (row, ui) in u.iter().enumerate().filter(|(_, ui)| **ui != 0.0) {
+            for (col, vj) in v.iter().enumerate().filter(|(_, vj)| **vj != 0.0) {
+                self._add_at(row as u64, col as u64, alpha * ui * vj);
+            }
+        }
+        self._invalidate_compressed();
+    }
+
+    // self += alpha * sum_k u_k v_k^T. Contributions are summed per position first, so each
+    // touched entry is written (and pruned) once rather than once per k.
+    #[allow(dead_code)]
+    pub fn rank_k_update(&mut self, alpha: f64, u_cols: &[Vec<f64>], v_cols: &[Vec<f64>]) {
+        assert!(u_cols.len() == v_cols.len());
+        let mut deltas: HashMap<(u64, u64), f64> = HashMap::new();
+        for (u, v) in std::iter::zip(u_cols, v_cols) {
+            assert!(u.len() as u64 == self.shape.0);
+            assert!(v.len() as u64 == self.shape.1);
+            for (row, ui) in u.iter().enumerate().filter(|(_, ui)| **ui != 0.0) {
+                for (col, vj) in v.iter().enumerate().filter(|(_, vj)| **vj != 0.0) {
+                    *deltas.entry((row as u64, col as u64)).or_insert(0.0) += ui * vj;
+                }
+            }
+        }
+        for ((row, col), delta) in deltas {
+            self._add_at(row, col, alpha * delta);
+        }
+        self._invalidate_compressed();
+    }
+
+    // Adds v[col] to every row. Each nonzero v[col] densifies that whole column.
+    #[allow(dead_code)]
+    pub fn add_row_vector(&mut self, v: &[f64]) {
+        assert!(v.len() as u64 == self.shape.1);
+        for (col, delta) in v.iter().enumerate() {
+            if *delta == 0.0 {
+                continue;
+            }
+            for row in 0..self.shape.0 {
+                self._add_at(row, col as u64, *delta);
+            }
+        }
+        self._invalidate_compressed();
+    }
+
+    // Adds v[row] to every column. Each nonzero v[row] densifies that whole row.
+    #[allow(dead_code)]
+    pub fn add_col_vector(&mut self, v: &[f64]) {
+        assert!(v.len() as u64 == self.shape.0);
+        for (row, delta) in v.iter().enumerate() {
+            if *delta == 0.0 {
+                continue;
+            }
+            for col in 0..self.shape.1 {
+                self._add_at(row as u64, col, *delta);
+            }
+        }
+        self._invalidate_compressed();
+    }
+
+    // Folds a same-shape dense matrix in place; zero cells are skipped, so the pattern
+    // only grows where the dense side is nonzero
+    #[allow(dead_code)]
+    pub fn add_dense(&mut self, dense: &[Vec<f64>]) {
+        assert!(dense.len() as u64 == self.shape.0);
+        for (row, dense_row) in dense.iter().enumerate() {
+            assert!(dense_row.len() as u64 == self.shape.1);
+            for (col, delta) in dense_row.iter().enumerate() {
+                if *delta != 0.0 {
+                    self._add_at(row as u64, col as u64, *delta);
+                }
+            }
+        }
+        self._invalidate_compressed();
+    }
+
+    // Accumulate into an entry, dropping it if the sum cancels to zero
+    fn _add_at(&mut self, row: u64, col: u64, delta: f64) {
+        let newval = self.values.get(&(row, col)).copied().unwrap_or(0.0) + delta;
+        if newval == 0.0 {
+            self.values.remove(&(row, col));
+        } else {
+            self.values.insert((row, col), newval);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn matvec(&self, x: &[f64]) -> Vec<f64> {
+        let mut y = vec![0.0; self.shape.0 as usize];
+        self.matvec_into(1.0, x, &mut y);
+        y
+    }
+
+    // y += alpha * A * x without allocating the product
+    #[allow(dead_code)]
+    pub fn matvec_into(&self, alpha: f64, x: &[f64], y: &mut [f64]) {
+        assert!(x.len() as u64 == self.shape.1);
+        assert!(y.len() as u64 == self.shape.0);
+        self._with_compressed(|rowarray, colarray, dataarray| {
+            for (row, yi) in y.iter_mut().enumerate() {
+                let start = rowarray[row] as usize;
+                let end = rowarray[row + 1] as usize;
+                let dot: f64 = std::iter::zip(&colarray[start..end], &dataarray[start..end])
+                    .map(|(col, val)| val * x[*col as usize])
+                    .sum();
+                *yi += alpha * dot;
+            }
+        })
+    }
+
+    // A^T * x by scattering each CSR row's entries, without building the transpose
+    #[allow(dead_code)]
+    pub fn matvec_transpose(&self, x: &[f64]) -> Vec<f64> {
+        assert!(x.len() as u64 == self.shape.0);
+        self._with_compressed(|rowarray, colarray, dataarray| {
+            let mut out = vec![0.0; self.shape.1 as usize];
+            for (row, xi) in x.iter().enumerate() {
+                let start = rowarray[row] as usize;
+                let end = rowarray[row + 1] as usize;
+                for (col, val) in std::iter::zip(&colarray[start..end], &dataarray[start..end]) {
+                    out[*col as usize] += val * xi;
+                }
+            }
+            out
+        })
+    }
+
+    // sqrt(x^T A x) for SPD A; NaN if x^T A x is negative (A not positive semi-definite)
+    #[allow(dead_code)]
+    pub fn energy_norm(&self, x: &[f64]) -> f64 {
+        assert!(self.shape.0 == self.shape.1);
+        _dot(x, &self.matvec(x)).sqrt()
+    }
+
+    // Matvec over the cached band storage, streaming each diagonal. None unless
+    // explicitly_compress found the matrix square with a band (2 * bandwidth + 1) at most
+    // a quarter of the columns wide and half filled; use matvec then.
+    #[allow(dead_code)]
+    pub fn matvec_banded(&self, x: &[f64]) -> Option<Vec<f64>> {
+        assert!(x.len() as u64 == self.shape.1);
+        if !self.compressed_updated {
+            return None;
+        }
+        let (half, band) = self.band.as_ref()?;
+        let (half, n) = (*half, self.shape.0 as usize);
+
+        // One pass per diagonal: y[rows] += A[rows, rows + offset] * x[rows + offset], where
+        // all three slices are contiguous
+        let mut out = vec![0.0; n];
+        for (k, diagonal) in band.chunks_exact(n).enumerate() {
+            let (first_row, last_row) = if k < half {
+                (half - k, n)
+            } else {
+                (0, n - (k - half))
+            };
+            let first_col = first_row + k - half;
+            std::iter::zip(
+                &mut out[first_row..last_row],
+                std::iter::zip(
+                    &diagonal[first_row..last_row],
+                    &x[first_col..first_col + (last_row - first_row)],
+                ),
+            )
+            .for_each(|(yi, (a, xi))| *yi += a * xi);
+        }
+        Some(out)
+    }
+
+    // b - A*x
+    #[allow(dead_code)]
+    pub fn residual(&self, x: &[f64], b: &[f64]) -> Vec<f64> {
+        assert!(b.len() as u64 == self.shape.0);
+        let ax = self.matvec(x);
+        std::iter::zip(b, ax).map(|(bi, axi)| bi - axi).collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn residual_norm(&self, x: &[f64], b: &[f64]) -> f64 {
+        self.residual(x, b)
+            .iter()
+            .map(|r| r * r)
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    // Max absolute column sum
+    #[allow(dead_code)]
+    pub fn one_norm(&self) -> f64 {
+        let mut col_sums = vec![0.0; self.shape.1 as usize];
+        for ((_row, col), val) in self.values.iter() {
+            col_sums[*col as usize] += val.abs();
+        }
+        col_sums.into_iter().fold(0.0, f64::max)
+    }
+
+    // (Preconditioned) conjugate gradient for SPD systems, starting from x = 0. Converged
+    // once ||b - Ax|| <= tol * ||b||; None on breakdown or if max_iter is exhausted.
+    #[allow(dead_code)]
+    pub fn solve_cg(
+        &self,
+        b: &[f64],
+        max_iter: usize,
+        tol: f64,
+        preconditioner: Option<&JacobiPreconditioner>,
+    ) -> Option<Vec<f64>> {
+        let (x, report) = self.solve_cg_reported(b, max_iter, tol, preconditioner);
+        report.converged.then_some(x)
+    }
+
+    // Non-finite input would spread NaN through every iterate, so the solvers stop before
+    // the first one and report x = 0 as unconverged
+    fn _refused_report(b: &[f64]) -> SolveReport {
+        SolveReport {
+            iterations: 0,
+            final_residual: _norm2(b),
+            converged: false,
+        }
+    }
+
+    // As solve_cg, but always returns the last iterate alongside how the solve went
+    #[allow(dead_code)]
+    pub fn solve_cg_reported(
+        &self,
+        b: &[f64],
+        max_iter: usize,
+        tol: f64,
+        preconditioner: Option<&JacobiPreconditioner>,
+    ) -> (Vec<f64>, SolveReport) {
+        assert!(self.shape.0 == self.shape.1);
+        assert!(b.len() as u64 == self.shape.0);
+        let n = b.len();
+        if self.has_non_finite() || b.iter().any(|v| !v.is_finite()) {
+            return (vec![0.0; n], Self::_refused_report(b));
+        }
+        let precondition = |r: &[f64]| match preconditioner {
+            Some(m) => m.apply(r),
+            None => r.to_vec(),
+        };
+
+        let threshold = tol * _norm2(b);
+        let mut x = vec![0.0; n];
+        let mut r = b.to_vec();
+        let mut report = SolveReport {
+            iterations: 0,
+            final_residual: _norm2(&r),
+            converged: false,
+        };
+        if report.final_residual <= threshold {
+            report.converged = true;
+            return (x, report);
+        }
+        let mut z = precondition(&r);
+        let mut p = z.clone();
+        let mut rz = _dot(&r, &z);
+
+        while report.iterations < max_iter {
+            let ap = self.matvec(&p);
+            let pap = _dot(&p, &ap);
+            if pap == 0.0 {
+                break;
+            }
+            report.iterations += 1;
+            let alpha = rz / pap;
+            for idx in 0..n {
+                x[idx] += alpha * p[idx];
+                r[idx] -= alpha * ap[idx];
+            }
+            report.final_residual = _norm2(&r);
+            if report.final_residual <= threshold {
+                report.converged = true;
+                break;
+            }
+
+            z = precondition(&r);
+            let rz_next = _dot(&r, &z);
+            let beta = rz_next / rz;
+            for idx in 0..n {
+                p[idx] = z[idx] + beta * p[idx];
+            }
+            rz = rz_next;
+        }
+        (x, report)
+    }
+
+    // BiCGSTAB for general square systems, starting from x = 0. Converged once
+    // ||b - Ax|| <= tol * ||b||; None on breakdown or if max_iter is exhausted.
+    #[allow(dead_code)]
+    pub fn solve_bicgstab(&self, b: &[f64], max_iter: usize, tol: f64) -> Option<Vec<f64>> {
+        let (x, report) = self.solve_bicgstab_reported(b, max_iter, tol);
+        report.converged.then_some(x)
+    }
+
+    // As solve_bicgstab, but always returns the last iterate alongside how the solve went
+    #[allow(dead_code)]
+    pub fn solve_bicgstab_reported(
+        &self,
+        b: &[f64],
+        max_iter: usize,
+        tol: f64,
+    ) -> (Vec<f64>, SolveReport) {
+        assert!(self.shape.0 == self.shape.1);
+        assert!(b.len() as u64 == self.shape.0);
+        let n = b.len();
+        if self.has_non_finite() || b.iter().any(|v| !v.is_finite()) {
+            return (vec![0.0; n], Self::_refused_report(b));
+        }
+
+        let threshold = tol * _norm2(b);
+        let mut x = vec![0.0; n];
+        let mut r = b.to_vec();
+        let mut report = SolveReport {
+            iterations: 0,
+            final_residual: _norm2(&r),
+            converged: false,
+        };
+        if report.final_residual <= threshold {
+            report.converged = true;
+            return (x, report);
+        }
+        let r_hat = r.clone();
+        let (mut rho, mut alpha, mut omega) = (1.0, 1.0, 1.0);
+        let mut v = vec![0.0; n];
+        let mut p = vec![0.0; n];
+
+        while report.iterations < max_iter {
+            let rho_next = _dot(&r_hat, &r);
+            if rho_next == 0.0 {
+                break;
+            }
+            let beta = (rho_next / rho) * (alpha / omega);
+            for idx in 0..n {
+                p[idx] = r[idx] + beta * (p[idx] - omega * v[idx]);
+            }
+            v = self.matvec(&p);
+            let denom = _dot(&r_hat, &v);
+            if denom == 0.0 {
+                break;
+            }
+            report.iterations += 1;
+            alpha = rho_next / denom;
+
+            let s: Vec<f64> = std::iter::zip(&r, &v)
+                .map(|(ri, vi)| ri - alpha * vi)
+                .collect();
+            if _norm2(&s) <= threshold {
+                for idx in 0..n {
+                    x[idx] += alpha * p[idx];
+                }
+                report.final_residual = _norm2(&s);
+                report.converged = true;
+                break;
+            }
+
+            let t = self.matvec(&s);
+            let tt = _dot(&t, &t);
+            if tt == 0.0 {
+                break;
+            }
+            omega = _dot(&t, &s) / tt;
+            for idx in 0..n {
+                x[idx] += alpha * p[idx] + omega * s[idx];
+                r[idx] = s[idx] - omega * t[idx];
+            }
+            report.final_residual = _norm2(&r);
+            if report.final_residual <= threshold {
+                report.converged = true;
+                break;
+            }
+            if omega == 0.0 {
+                break;
+            }
+            rho = rho_next;
+        }
+        (x, report)
+    }
+
+    // Dense LU with partial pivoting; None if singular
+    #[allow(dead_code)]
+    pub fn factor_lu(&self) -> Option<LuFactorization> {
+        assert!(self.shape.0 == self.shape.1);
+        let n = self.shape.0 as usize;
+        let mut dense = self.to_dense();
+        let mut perm: Vec<usize> = (0..n).collect();
+
+        for pivot_idx in 0..n {
+            let pivot_row = (pivot_idx..n)
+                .max_by(|a, b| {
+                    dense[*a][pivot_idx]
+                        .abs()
+                        .total_cmp(&dense[*b][pivot_idx].abs())
+                })
+                .unwrap();
+            // Only an exactly zero pivot means singular; ill-conditioning is left to
+            // condition_estimate rather than guessed at with a cutoff
+            let pivot = dense[pivot_row][pivot_idx];
+            if pivot == 0.0 || !pivot.is_finite() {
+                return None;
+            }
+            dense.swap(pivot_idx, pivot_row);
+            perm.swap(pivot_idx, pivot_row);
+
+            for row in pivot_idx + 1..n {
+                let factor = dense[row][pivot_idx] / dense[pivot_idx][pivot_idx];
+                // L multipliers live below the diagonal, U on and above it
+                dense[row][pivot_idx] = factor;
+                if factor == 0.0 {
+                    continue;
+                }
+                let (upper, lower) = dense.split_at_mut(row);
+                for (target, source) in std::iter::zip(
+                    &mut lower[0][pivot_idx + 1..],
+                    &upper[pivot_idx][pivot_idx + 1..],
+                ) {
+                    *target -= factor * source;
+                }
+            }
+        }
+        Some(LuFactorization { lu: dense, perm })
+    }
+
+    // Lower-triangular L with A = L L^T, computed with a dense kernel. None unless A is SPD.
+    // Fill-in shows up as extra entries of L, which is what orderings try to minimise.
+    #[allow(dead_code)]
+    pub fn cholesky(&self) -> Option<SparseMatrix> {
+        assert!(self.shape.0 == self.shape.1);
+        let n = self.shape.0 as usize;
+        let dense = self.to_dense();
+        let mut l = vec![vec![0.0; n]; n];
+
+        for col in 0..n {
+            let diag = dense[col][col] - _dot(&l[col][..col], &l[col][..col]);
+            if diag <= 0.0 || !diag.is_finite() {
+                return None;
+            }
+            let diag = diag.sqrt();
+            l[col][col] = diag;
+            for row in col + 1..n {
+                let val = (dense[row][col] - _dot(&l[row][..col], &l[col][..col])) / diag;
+                l[row][col] = val;
+            }
+        }
+
+        let mut local = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        for (row, values) in l.iter().enumerate() {
+            for (col, val) in values.iter().enumerate().take(row + 1) {
+                if *val != 0.0 {
+                    local.values.insert((row as u64, col as u64), *val);
+                }
+            }
+        }
+        Some(local)
+    }
+
+    #[allow(dead_code)]
+    pub fn solve_dense(&self, b: &[f64]) -> Option<Vec<f64>> {
+        assert!(b.len() as u64 == self.shape.0);
+        Some(self.factor_lu()?.solve(b))
+    }
+
+    // Sum of |v| over stored entries, as opposed to the max-column-sum one_norm
+    #[allow(dead_code)]
+    pub fn l1_norm_entrywise(&self) -> f64 {
+        self.values.values().map(|val| val.abs()).sum()
+    }
+
+    // ||A||_1 * ||A^-1||_1, with the inverse norm estimated by Hager's method
+    #[allow(dead_code)]
+    pub fn condition_estimate(&self) -> Option<f64> {
+        assert!(self.shape.0 == self.shape.1);
+        let n = self.shape.0 as usize;
+        if n == 0 {
+            return None;
+        }
+        // One factorization serves both A^-1 and A^-T for every iteration
+        let lu = self.factor_lu()?;
+
+        let mut x = vec![1.0 / n as f64; n];
+        let mut inverse_norm = 0.0;
+        for _ in 0..5 {
+            let y = lu.solve(&x);
+            inverse_norm = y.iter().map(|v| v.abs()).sum();
+            let signs: Vec<f64> = y
+                .iter()
+                .map(|v| if *v >= 0.0 { 1.0 } else { -1.0 })
+                .collect();
+            let z = lu.solve_transpose(&signs);
+
+            let (max_idx, max_z) = z
+                .iter()
+                .map(|v| v.abs())
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            let ztx: f64 = std::iter::zip(&z, &x).map(|(a, b)| a * b).sum();
+            if max_z <= ztx {
+                break;
+            }
+            x = vec![0.0; n];
+            x[max_idx] = 1.0;
+        }
+        Some(self.one_norm() * inverse_norm)
+    }
+
+    #[allow(dead_code)]
+    pub fn diagonal(&self) -> Vec<f64> {
+        (0..self.shape.0.min(self.shape.1))
+            .map(|idx| self.values.get(&(idx, idx)).copied().unwrap_or(0.0))
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn trace(&self) -> f64 {
+        assert!(self.shape.0 == self.shape.1);
+        (0..self.shape.0)
+            .filter_map(|idx| self.values.get(&(idx, idx)))
+            .sum()
+    }
+
+    // trace(self * other) = sum_ij a_ij * b_ji, so only pairs with both sides stored contribute
+    #[allow(dead_code)]
+    pub fn trace_of_product(&self, other: &SparseMatrix) -> f64 {
+        assert!(self.shape.1 == other.shape.0);
+        assert!(self.shape.0 == other.shape.1);
+        self.values
+            .iter()
+            .filter_map(|((row, col), val)| other.values.get(&(*col, *row)).map(|o| val * o))
+            .sum()
+    }
+
+    // f over the union of both patterns, absent entries read as 0.0; zero results are dropped
+    #[allow(dead_code)]
+    pub fn zip_with<F: Fn(f64, f64) -> f64>(&self, other: &SparseMatrix, f: F) -> SparseMatrix {
+        assert!(self.shape == other.shape);
+        let mut local = SparseMatrix::empty_with_shape(self.shape.0, self.shape.1);
+        for (key, val) in self.values.iter() {
+            let result = f(*val, other.values.get(key).copied().unwrap_or(0.0));
+            if result != 0.0 {
+                local.values.insert(*key, result);
+            }
+        }
+        for (key, val) in other.values.iter() {
+            if self.values.contains_key(key) {
+                continue;
+            }
+            let result = f(0.0, *val);
+            if result != 0.0 {
+                local.values.insert(*key, result);
+            }
+        }
+        local
+    }
+
+    // (1 - t) * self + t * other over the union of patterns; t must lie in [0, 1]
+    #[allow(dead_code)]
+    pub fn lerp(&self, other: &SparseMatrix, t: f64) -> SparseMatrix {
+        assert!((0.0..=1.0).contains(&t));
+        self.zip_with(other, |a, b| (1.0 - t) * a + t * b)
+    }
+
+    // Union of both patterns; where only one side stores a value it is kept as-is rather
+    // than compared against an implicit 0.0, so a lone negative weight survives the merge
+    #[allow(dead_code)]
+    pub fn merge_max(&self, other: &SparseMatrix) -> SparseMatrix {
+        assert!(self.shape == other.shape);
+        let mut local = self.clone();
+        for (key, val) in other.values.iter() {
+            local
+                .values
+                .entry(*key)
+                .and_modify(|existing| *existing = existing.max(*val))
+                .or_insert(*val);
+        }
+        local._invalidate_compressed();
+        local
+    }
+
+    #[allow(dead_code)]
+    pub fn frobenius_norm(&self) -> f64 {
+        self.values.values().map(|v| v * v).sum::<f64>().sqrt()
+    }
+
+    // Frobenius norm over only the positions where mask holds a nonzero
+    #[allow(dead_code)]
+    pub fn masked_frobenius(&self, mask: &SparseMatrix) -> f64 {
+        assert!(self.shape == mask.shape);
+        let sum_sq: f64 = if self.values.len() <= mask.values.len() {
+            self.values
+                .iter()
+                .filter(|(key, _)| mask.values.get(key).is_some_and(|m| *m != 0.0))
+                .map(|(_, val)| val * val)
+                .sum()
+        } else {
+            mask.values
+                .iter()
+                .filter(|(_, m)| **m != 0.0)
+                .filter_map(|(key, _)| self.values.get(key))
+                .map(|val| val * val)
+                .sum()
+        };
+        sum_sq.sqrt()
+    }
+
+    #[allow(dead_code)]
+    pub fn frobenius_inner(&self, other: &SparseMatrix) -> f64 {
+        assert!(self.shape == other.shape);
+        // Walk the smaller pattern and probe the other
+        let (small, large) = if self.values.len() <= other.values.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        small
+            .values
+            .iter()
+            .filter_map(|(key, val)| large.values.get(key).map(|o| val * o))
+            .sum()
+    }
+
+    // Same traversal as spgemm but accumulating straight into a dense result, for products
+    // that are expected to be mostly nonzero anyway
+    #[allow(dead_code)]
+    pub fn mul_dense(&self, other: &SparseMatrix) -> Vec<Vec<f64>> {
+        assert!(self.shape.1 == other.shape.0);
+        let mut dense = vec![vec![0.0; other.shape.1 as usize]; self.shape.0 as usize];
+
+        self._with_compressed(|a_rows, a_cols, a_data| {
+            other._with_compressed(|b_rows, b_cols, b_data| {
+                for (row, out) in dense.iter_mut().enumerate() {
+                    for a_idx in a_rows[row] as usize..a_rows[row + 1] as usize {
+                        let inner = a_cols[a_idx] as usize;
+                        for b_idx in b_rows[inner] as usize..b_rows[inner + 1] as usize {
+                            out[b_cols[b_idx] as usize] += a_data[a_idx] * b_data[b_idx];
+                        }
+                    }
+                }
+            })
+        });
+        dense
+    }
+
+    // A^n by repeated squaring. Each product can fill in, so powers of even a very sparse
+    // matrix quickly approach dense (e.g. A^k of a connected graph's adjacency).
+    #[allow(dead_code)]
+    pub fn pow(&self, exponent: u32) -> SparseMatrix {
+        assert!(self.shape.0 == self.shape.1);
+        let mut result: Option<SparseMatrix> = None;
+        let mut base = self.clone();
+        let mut remaining = exponent;
+        while remaining > 0 {
+            if remaining & 1 == 1 {
+                result = Some(match result {
+                    None => base.clone(),
+                    Some(acc) => acc.spgemm(&base),
+                });
+            }
+            remaining >>= 1;
+            if remaining > 0 {
+                base = base.spgemm(&base);
+            }
+        }
+        result.unwrap_or_else(|| SparseMatrix::identity(self.shape.0))
+    }
+
+    // Entry (i, j) is the number of directed walks of the given length from i to j
+    #[allow(dead_code)]
+    pub fn path_counts(&self, length: u32) -> SparseMatrix {
+        self.threshold_mask(|v| v != 0.0).pow(length)
+    }
+
+    // Row-by-row (Gustavson) sparse product over the CSR forms of both operands
+    #[allow(dead_code)]
+    pub fn spgemm(&self, other: &SparseMatrix) -> SparseMatrix {
+        assert!(self.shape.1 == other.shape.0);
+        let mut local = SparseMatrix::empty_with_shape(self.shape.0, other.shape.1);
+
+        self._with_compressed(|a_rows, a_cols, a_data| {
+            other._with_compressed(|b_rows, b_cols, b_data| {
+                let mut accumulator = vec![0.0; other.shape.1 as usize];
+                let mut touched: Vec<u64> = vec![];
+                let mut is_touched = vec![false; other.shape.1 as usize];
+
+                for row in 0..self.shape.0 as usize {
+                    for a_idx in a_rows[row] as usize..a_rows[row + 1] as usize {
+                        let inner = a_cols[a_idx] as usize;
+                        let a_val = a_data[a_idx];
+                        for b_idx in b_rows[inner] as usize..b_rows[inner + 1] as usize {
+                            let col = b_cols[b_idx];
+                            accumulator[col as usize] += a_val * b_data[b_idx];
+                            if !is_touched[col as usize] {
+                                is_touched[col as usize] = true;
+                                touched.push(col);
+                            }
+                        }
+                    }
+
+                    for col in touched.drain(..) {
+                        let val = accumulator[col as usize];
+                        if val != 0.0 {
+                            local.values.insert((row as u64, col), val);
+                        }
+                        accumulator[col as usize] = 0.0;
+                        is_touched[col as usize] = false;
+                    }
+                }
+            })
+        });
+        local
+    }
+
+    // Symbolic nnz of self * other: the structural pattern only, so an entry that cancels to
+    // exactly zero still counts and this is an upper bound on the stored product
+    #[allow(dead_code)]
+    pub fn estimate_product_nnz(&self, other: &SparseMatrix) -> u64 {
+        assert!(self.shape.1 == other.shape.0);
+
+        self._with_compressed(|a_rows, a_cols, _| {
+            other._with_compressed(|b_rows, b_cols, _| {
+                // Stamp each column with the row that last reached it instead of clearing
+                let mut last_row = vec![usize::MAX; other.shape.1 as usize];
+                let mut count = 0;
+                for row in 0..self.shape.0 as usize {
+                    for inner in &a_cols[a_rows[row] as usize..a_rows[row + 1] as usize] {
+                        let inner = *inner as usize;
+                        for col in &b_cols[b_rows[inner] as usize..b_rows[inner + 1] as usize] {
+                            let col = *col as usize;
+                            if last_row[col] != row {
+                                last_row[col] = row;
+                                count += 1;
+                            }
+                        }
+                    }
+                }
+                count
+            })
+        })
+    }
+
+    // spgemm that refuses up front when the symbolic fill would exceed max_nnz
+    #[allow(dead_code)]
+    pub fn try_mul_bounded(
+        &self,
+        other: &SparseMatrix,
+        max_nnz: u64,
+    ) -> Result<SparseMatrix, SparseError> {
+        _check_dimension(self.shape.1, other.shape.0)?;
+        let estimate = self.estimate_product_nnz(other);
+        if estimate > max_nnz {
+            return Err(SparseError::TooManyNonzeros {
+                estimate,
+                limit: max_nnz,
+            });
+        }
+        Ok(self.spgemm(other))
+    }
+
+    // Entries in the CSR form handed to solvers; excludes explicit zeros, unlike num_nonzero
+    #[allow(dead_code)]
+    pub fn nnz_compressed(&self) -> u64 {
+        self._with_compressed(|_, _, dataarray| dataarray.len() as u64)
+    }
+
+    #[allow(dead_code)]
+    pub fn transpose_inplace(&mut self) {
         // Naive impl, could do better
         self.shape = (self.shape.1, self.shape.0);
 
@@ -201,7 +2582,27 @@ impl SparseMatrix {
         for ((row, col), val) in triplets {
             self.values.insert((col, row), val);
         }
-        self.compressed_updated = false;
+        self._invalidate_compressed();
+    }
+}
+
+// Same overwrite semantics as insert_triplets: a repeated coordinate keeps the last value
+impl Extend<(u64, u64, f64)> for SparseMatrix {
+    fn extend<T: IntoIterator<Item = (u64, u64, f64)>>(&mut self, iter: T) {
+        for (row, col, val) in iter {
+            assert!(row < self.shape.0);
+            assert!(col < self.shape.1);
+
+            self._store(row, col, val);
+        }
+        self._invalidate_compressed();
+    }
+}
+
+// Structural comparison of the stored entries; an explicit 0.0 counts as an entry
+impl PartialEq for SparseMatrix {
+    fn eq(&self, other: &SparseMatrix) -> bool {
+        self.shape == other.shape && self.values == other.values
     }
 }
 
@@ -241,6 +2642,15 @@ impl Add for &SparseMatrix {
 
     fn add(self, other: &SparseMatrix) -> SparseMatrix {
         assert!(self.shape == other.shape);
+        // The shortcuts would drop stored zeros and could hand back other's flag
+        if !self.keep_explicit_zeros && !other.keep_explicit_zeros {
+            if other.is_zero() {
+                return self.clone();
+            }
+            if self.is_zero() {
+                return other.clone();
+            }
+        }
         let mut local = self.clone();
 
         for ((rother, cother), elemother) in other.values.iter() {
@@ -250,3 +2660,61 @@ impl Add for &SparseMatrix {
         local
     }
 }
+
+use std::ops::Sub;
+
+impl Sub for &SparseMatrix {
+    type Output = SparseMatrix;
+
+    fn sub(self, other: &SparseMatrix) -> SparseMatrix {
+        assert!(self.shape == other.shape);
+        // Same explicit-zero caveat as Add
+        if !self.keep_explicit_zeros && !other.keep_explicit_zeros {
+            if other.is_zero() {
+                return self.clone();
+            }
+            if self.is_zero() {
+                return other * -1.0;
+            }
+        }
+        let mut local = self.clone();
+
+        for ((rother, cother), elemother) in other.values.iter() {
+            let existingval = local.peek_at(*rother, *cother).unwrap_or(0.0);
+            local.insert(*rother, *cother, existingval - *elemother);
+        }
+        local
+    }
+}
+
+use std::ops::Mul;
+
+impl Mul for &SparseMatrix {
+    type Output = SparseMatrix;
+
+    fn mul(self, other: &SparseMatrix) -> SparseMatrix {
+        self.spgemm(other)
+    }
+}
+
+impl Mul<f64> for &SparseMatrix {
+    type Output = SparseMatrix;
+
+    fn mul(self, factor: f64) -> SparseMatrix {
+        let mut local = self.clone();
+        local.scale(factor);
+        local
+    }
+}
+
+use std::ops::Div;
+
+// Scales by the reciprocal; dividing by zero panics rather than filling with inf/NaN
+impl Div<f64> for &SparseMatrix {
+    type Output = SparseMatrix;
+
+    fn div(self, divisor: f64) -> SparseMatrix {
+        assert!(divisor != 0.0);
+        self * (1.0 / divisor)
+    }
+}
@@ -1,4 +1,9 @@
-use sparse_mat::sparse_matrix::SparseMatrix;
+use std::collections::HashMap;
+
+use sparse_mat::sparse_matrix::{
+    EdgeCombine, IndexBase, JacobiPreconditioner, LuFactorization, SolveReport, SparseError,
+    SparseMatrix, StorageHint, TransposeView,
+};
 
 #[test]
 fn sparsemat_creation() {
@@ -200,3 +205,2096 @@ fn sparsemat_good_addition() {
     assert!(local3.peek_at(1, 1) == Some(60.0));
     assert!(local3.peek_at(2, 2) == Some(100.0));
 }
+
+#[test]
+fn sparsemat_sorted_entries() {
+    let triplets = vec![
+        (2, 4, 70.0),
+        (0, 1, 20.0),
+        (3, 5, 80.0),
+        (1, 3, 40.0),
+        (0, 0, 10.0),
+        (2, 2, 50.0),
+        (1, 1, 30.0),
+        (2, 3, 60.0),
+    ];
+
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(triplets.clone());
+    let entries = local.sorted_entries();
+    assert!(
+        entries
+            == vec![
+                ((0, 0), 10.0),
+                ((0, 1), 20.0),
+                ((1, 1), 30.0),
+                ((1, 3), 40.0),
+                ((2, 2), 50.0),
+                ((2, 3), 60.0),
+                ((2, 4), 70.0),
+                ((3, 5), 80.0),
+            ]
+    );
+
+    for _ in 0..10 {
+        let mut local2 = SparseMatrix::empty_with_shape(4, 6);
+        local2.insert_triplets(triplets.clone());
+        assert!(local2.sorted_entries() == entries);
+    }
+}
+
+#[test]
+fn sparsemat_graph_degrees() {
+    // 0 -> 1, 0 -> 2, 1 -> 2, 2 -> 0, node 3 has no edges
+    let mut local = SparseMatrix::empty_with_shape(4, 4);
+    local.insert_triplets(vec![(0, 1, 1.0), (0, 2, 2.5), (1, 2, 1.0), (2, 0, 3.0)]);
+
+    assert!(local.out_degree() == vec![2, 1, 1, 0]);
+    assert!(local.in_degree() == vec![1, 1, 2, 0]);
+    assert!(local.isolated_nodes() == vec![3]);
+}
+
+#[test]
+fn sparsemat_reachable_from() {
+    // 0 -> 1 -> 2 -> 0, 3 -> 4, 4 -> 2; nothing flows back into 3 or 4
+    let mut local = SparseMatrix::empty_with_shape(5, 5);
+    local.insert_triplets(vec![
+        (0, 1, 1.0),
+        (1, 2, 1.0),
+        (2, 0, 1.0),
+        (3, 4, 1.0),
+        (4, 2, 1.0),
+    ]);
+
+    let mut reached = local.reachable_from(0);
+    reached.sort();
+    assert!(reached == vec![0, 1, 2]);
+
+    let mut reached = local.reachable_from(3);
+    reached.sort();
+    assert!(reached == vec![0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn sparsemat_permute_rows_cols() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (0, 2, 2.0), (1, 1, 3.0), (2, 0, 4.0)]);
+
+    let rows = local.permute_rows(&[2, 0, 1]);
+    assert!(rows.peek_at(0, 0) == Some(4.0));
+    assert!(rows.peek_at(1, 0) == Some(1.0));
+    assert!(rows.peek_at(1, 2) == Some(2.0));
+    assert!(rows.peek_at(2, 1) == Some(3.0));
+
+    let cols = local.permute_cols(&[2, 0, 1]);
+    assert!(cols.peek_at(0, 0) == Some(2.0));
+    assert!(cols.peek_at(0, 1) == Some(1.0));
+    assert!(cols.peek_at(1, 2) == Some(3.0));
+    assert!(cols.peek_at(2, 1) == Some(4.0));
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_permute_rows_invalid() {
+    let local = SparseMatrix::identity(3);
+    let _ = local.permute_rows(&[0, 0, 1]);
+}
+
+#[test]
+fn sparsemat_rcm_permutation() {
+    // Tridiagonal (path graph) with its node labels scrambled
+    let labels: Vec<u64> = vec![5, 2, 7, 0, 3, 6, 1, 4];
+    let mut local = SparseMatrix::empty_with_shape(8, 8);
+    for idx in 0..labels.len() {
+        let node = labels[idx];
+        local.insert(node, node, 4.0);
+        if idx + 1 < labels.len() {
+            let next = labels[idx + 1];
+            local.insert(node, next, -1.0);
+            local.insert(next, node, -1.0);
+        }
+    }
+
+    let perm = local.rcm_permutation();
+    let reordered = local.permute_rows(&perm).permute_cols(&perm);
+    assert!(reordered.bandwidth() < local.bandwidth());
+    assert!(reordered.bandwidth() == 1);
+    assert!(reordered.num_nonzero() == local.num_nonzero());
+}
+
+#[test]
+fn sparsemat_round_to() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 1.2345), (1, 1, -2.6789), (2, 2, 0.0012)]);
+
+    local.round_to(2);
+    assert!(local.peek_at(0, 0) == Some(1.23));
+    assert!(local.peek_at(1, 1) == Some(-2.68));
+    assert!(local.peek_at(2, 2).is_none());
+    assert!(local.num_nonzero() == 2);
+
+    // 10^400 overflows and 1e9 * 10^300 does too; neither may turn into NaN or inf
+    let mut local = SparseMatrix::identity(2);
+    local.round_to(400);
+    assert!(local == SparseMatrix::identity(2));
+
+    let mut local = SparseMatrix::empty_with_shape(2, 2);
+    local.insert_triplets(vec![(0, 0, 1e9), (1, 1, 0.5)]);
+    local.round_to(300);
+    assert!(local.peek_at(0, 0) == Some(1e9));
+    assert!(local.peek_at(1, 1) == Some(0.5));
+}
+
+#[test]
+fn sparsemat_random() {
+    let local = SparseMatrix::random((50, 40), 0.1, 42);
+    assert!(local.shape == (50, 40));
+    assert!(local.num_nonzero() == 200);
+    for (_, val) in local.sorted_entries() {
+        assert!((-1.0..1.0).contains(&val));
+    }
+
+    let local2 = SparseMatrix::random((50, 40), 0.1, 42);
+    assert!(local.sorted_entries() == local2.sorted_entries());
+
+    let local3 = SparseMatrix::random((50, 40), 0.1, 7);
+    assert!(local.sorted_entries() != local3.sorted_entries());
+}
+
+#[test]
+fn sparsemat_symmetric_dominant_checks() {
+    let mut local = SparseMatrix::empty_with_shape(2, 2);
+    local.insert_triplets(vec![(0, 0, 2.0), (0, 1, 3.0), (1, 0, 3.0), (1, 1, 4.0)]);
+    assert!(local.is_symmetric());
+    assert!(!local.is_diagonally_dominant());
+
+    local.insert(1, 0, 1.0);
+    assert!(!local.is_symmetric());
+
+    assert!(SparseMatrix::identity(3).is_diagonally_dominant());
+    assert!(!SparseMatrix::empty_with_shape(2, 3).is_symmetric());
+}
+
+#[test]
+fn sparsemat_random_spd() {
+    let local = SparseMatrix::random_spd(20, 0.15, 11);
+    assert!(local.shape == (20, 20));
+    assert!(local.is_symmetric());
+    assert!(local.is_diagonally_dominant());
+    for idx in 0..20 {
+        assert!(local.peek_at(idx, idx).unwrap() > 0.0);
+    }
+}
+
+#[test]
+fn sparsemat_compressed_deterministic() {
+    let build = || {
+        let mut local = SparseMatrix::empty_with_shape(4, 6);
+        local.insert_triplets(vec![
+            (3, 5, 80.0),
+            (2, 4, 70.0),
+            (0, 1, 20.0),
+            (1, 3, 40.0),
+            (2, 2, 50.0),
+            (0, 0, 10.0),
+            (2, 3, 60.0),
+            (1, 1, 30.0),
+        ]);
+        local.explicitly_compress();
+        local
+    };
+
+    let reference = build();
+    for _ in 0..10 {
+        let local = build();
+        assert!(local.compressed_rowarray == reference.compressed_rowarray);
+        assert!(local.compressed_colarray == reference.compressed_colarray);
+        assert!(local.compressed_dataarray == reference.compressed_dataarray);
+    }
+}
+
+#[test]
+fn sparsemat_add_row_col_vector() {
+    let mut local = SparseMatrix::empty_with_shape(2, 3);
+    local.insert_triplets(vec![(0, 0, 2.0), (1, 2, -1.0)]);
+
+    local.add_row_vector(&[1.0, 1.0, 1.0]);
+    assert!(local.peek_at(0, 0) == Some(3.0));
+    assert!(local.peek_at(0, 1) == Some(1.0));
+    assert!(local.peek_at(0, 2) == Some(1.0));
+    assert!(local.peek_at(1, 0) == Some(1.0));
+    assert!(local.peek_at(1, 1) == Some(1.0));
+    assert!(local.peek_at(1, 2).is_none());
+    assert!(local.num_nonzero() == 5);
+
+    local.add_col_vector(&[0.0, -1.0]);
+    assert!(local.peek_at(0, 0) == Some(3.0));
+    assert!(local.peek_at(1, 0).is_none());
+    assert!(local.peek_at(1, 1).is_none());
+    assert!(local.peek_at(1, 2) == Some(-1.0));
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_add_row_vector_bad_length() {
+    let mut local = SparseMatrix::empty_with_shape(2, 3);
+    local.add_row_vector(&[1.0, 1.0]);
+}
+
+#[test]
+fn sparsemat_spgemm() {
+    let mut local = SparseMatrix::empty_with_shape(2, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (0, 2, 2.0), (1, 1, 3.0)]);
+    let mut local2 = SparseMatrix::empty_with_shape(3, 2);
+    local2.insert_triplets(vec![(0, 0, 4.0), (1, 0, 5.0), (2, 0, -2.0), (2, 1, 6.0)]);
+
+    let local3 = &local * &local2;
+    assert!(local3.shape == (2, 2));
+    assert!(local3.peek_at(0, 0).is_none()); // 1*4 + 2*-2 cancels
+    assert!(local3.peek_at(0, 1) == Some(12.0));
+    assert!(local3.peek_at(1, 0) == Some(15.0));
+    assert!(local3.peek_at(1, 1).is_none());
+    assert!(local3.num_nonzero() == 2);
+}
+
+#[test]
+fn sparsemat_trace_of_product() {
+    let a = SparseMatrix::random((6, 6), 0.4, 3);
+    let b = SparseMatrix::random((6, 6), 0.4, 4);
+
+    assert!(SparseMatrix::identity(4).trace() == 4.0);
+    assert!((a.trace_of_product(&b) - (&a * &b).trace()).abs() < 1e-12);
+}
+
+#[test]
+fn sparsemat_frobenius_inner() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (0, 1, 2.0), (1, 2, 3.0), (2, 2, 4.0)]);
+    let mut local2 = SparseMatrix::empty_with_shape(3, 3);
+    local2.insert_triplets(vec![(0, 1, 5.0), (1, 2, -1.0), (2, 0, 7.0)]);
+
+    // Overlap is (0, 1) and (1, 2): 2*5 + 3*-1
+    assert!(local.frobenius_inner(&local2) == 7.0);
+    assert!(local2.frobenius_inner(&local) == 7.0);
+    assert!(local.frobenius_inner(&local2) == local.create_transpose().trace_of_product(&local2));
+}
+
+#[test]
+fn sparsemat_from_triplets_index_base() {
+    let local = SparseMatrix::from_triplets(
+        (3, 3),
+        vec![(1, 1, 1.0), (2, 3, 2.0), (3, 1, 3.0)],
+        IndexBase::One,
+    );
+    assert!(local.peek_at(0, 0) == Some(1.0));
+    assert!(local.peek_at(1, 2) == Some(2.0));
+    assert!(local.peek_at(2, 0) == Some(3.0));
+    assert!(local.num_nonzero() == 3);
+
+    assert!(local.to_triplets(IndexBase::One) == vec![(1, 1, 1.0), (2, 3, 2.0), (3, 1, 3.0)]);
+    assert!(local.to_triplets(IndexBase::Zero) == vec![(0, 0, 1.0), (1, 2, 2.0), (2, 0, 3.0)]);
+
+    let local2 = SparseMatrix::from_triplets((3, 3), vec![(0, 2, 5.0)], IndexBase::Zero);
+    assert!(local2.peek_at(0, 2) == Some(5.0));
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_from_triplets_one_based_zero_index() {
+    let _ = SparseMatrix::from_triplets((3, 3), vec![(0, 1, 1.0)], IndexBase::One);
+}
+
+#[test]
+fn sparsemat_non_finite() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (1, 1, 2.0)]);
+    assert!(!local.has_non_finite());
+
+    local.insert(2, 2, f64::NAN);
+    local.insert(0, 1, f64::INFINITY);
+    assert!(local.has_non_finite());
+
+    local.replace_non_finite(-1.0);
+    assert!(!local.has_non_finite());
+    assert!(local.peek_at(2, 2) == Some(-1.0));
+    assert!(local.peek_at(0, 1) == Some(-1.0));
+    assert!(local.peek_at(0, 0) == Some(1.0));
+
+    local.insert(2, 2, f64::NEG_INFINITY);
+    local.replace_non_finite(0.0);
+    assert!(local.peek_at(2, 2).is_none());
+    assert!(local.num_nonzero() == 3);
+}
+
+#[test]
+fn sparsemat_from_blocks() {
+    let a = SparseMatrix::identity(2);
+    let mut b = SparseMatrix::empty_with_shape(2, 2);
+    b.insert(0, 1, 2.0);
+    let mut c = SparseMatrix::empty_with_shape(2, 2);
+    c.insert(1, 0, 3.0);
+    let mut d = SparseMatrix::empty_with_shape(2, 2);
+    d.insert_triplets(vec![(0, 0, 4.0), (1, 1, 5.0)]);
+
+    let local = SparseMatrix::from_blocks(&a, &b, &c, &d).unwrap();
+    assert!(local.shape == (4, 4));
+    assert!(local.num_nonzero() == 6);
+    assert!(local.peek_at(0, 0) == Some(1.0));
+    assert!(local.peek_at(1, 1) == Some(1.0));
+    assert!(local.peek_at(0, 3) == Some(2.0));
+    assert!(local.peek_at(3, 0) == Some(3.0));
+    assert!(local.peek_at(2, 2) == Some(4.0));
+    assert!(local.peek_at(3, 3) == Some(5.0));
+}
+
+#[test]
+fn sparsemat_from_blocks_mismatch() {
+    let a = SparseMatrix::identity(2);
+    let b = SparseMatrix::empty_with_shape(3, 2);
+    let c = SparseMatrix::empty_with_shape(2, 2);
+    let d = SparseMatrix::empty_with_shape(2, 2);
+
+    let result = SparseMatrix::from_blocks(&a, &b, &c, &d);
+    assert!(
+        result.err()
+            == Some(SparseError::DimensionMismatch {
+                expected: 2,
+                found: 3
+            })
+    );
+}
+
+#[test]
+fn sparsemat_split_dlu() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![
+        (0, 0, 4.0),
+        (0, 2, -1.0),
+        (1, 0, 2.0),
+        (1, 1, 5.0),
+        (2, 1, 3.0),
+        (2, 2, 6.0),
+    ]);
+
+    let (d, l, u) = local.split_dlu();
+    assert!(d.sorted_entries() == vec![((0, 0), 4.0), ((1, 1), 5.0), ((2, 2), 6.0)]);
+    assert!(l.sorted_entries() == vec![((1, 0), 2.0), ((2, 1), 3.0)]);
+    assert!(u.sorted_entries() == vec![((0, 2), -1.0)]);
+
+    let rebuilt = &(&d + &l) + &u;
+    assert!(rebuilt.sorted_entries() == local.sorted_entries());
+}
+
+#[test]
+fn sparsemat_scalar_mul_div() {
+    let mut local = SparseMatrix::empty_with_shape(2, 2);
+    local.insert_triplets(vec![(0, 0, 4.0), (1, 0, -3.0)]);
+
+    let doubled = &local * 2.0;
+    assert!(doubled.peek_at(0, 0) == Some(8.0));
+    assert!(doubled.peek_at(1, 0) == Some(-6.0));
+
+    let halved = &local / 2.0;
+    assert!(halved.peek_at(0, 0) == Some(2.0));
+    assert!(halved.peek_at(1, 0) == Some(-1.5));
+    assert!(halved.num_nonzero() == 2);
+
+    let zeroed = &local * 0.0;
+    assert!(zeroed.num_nonzero() == 0);
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_scalar_div_by_zero() {
+    let local = SparseMatrix::identity(2);
+    let _ = &local / 0.0;
+}
+
+#[test]
+fn sparsemat_threshold_mask() {
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (2, 2, 50.0),
+        (1, 3, 40.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.0),
+    ]);
+
+    let mask = local.threshold_mask(|v| v > 50.0);
+    assert!(mask.shape == (4, 6));
+    assert!(mask.sorted_entries() == vec![((2, 3), 1.0), ((2, 4), 1.0), ((3, 5), 1.0)]);
+}
+
+#[test]
+fn sparsemat_pow() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (0, 1, 2.0), (1, 2, 3.0), (2, 0, -1.0)]);
+
+    assert!(local.pow(0) == SparseMatrix::identity(3));
+    assert!(local.pow(1) == local);
+    assert!(local.pow(2) == &local * &local);
+    assert!(local.pow(5) == &(&(&(&local * &local) * &local) * &local) * &local);
+}
+
+#[test]
+fn sparsemat_path_counts() {
+    // Directed 4-cycle 0 -> 1 -> 2 -> 3 -> 0, weights are ignored
+    let mut local = SparseMatrix::empty_with_shape(4, 4);
+    local.insert_triplets(vec![(0, 1, 5.0), (1, 2, 0.5), (2, 3, -2.0), (3, 0, 3.0)]);
+
+    let walks = local.path_counts(2);
+    assert!(
+        walks.sorted_entries() == vec![((0, 2), 1.0), ((1, 3), 1.0), ((2, 0), 1.0), ((3, 1), 1.0)]
+    );
+    assert!(local.path_counts(4) == SparseMatrix::identity(4));
+
+    // A chord 0 -> 2 opens a second walk of length 2 from 3 to 2 (3-0-2) and of length 3 from 0 to 0
+    local.insert(0, 2, 1.0);
+    assert!(local.path_counts(2).peek_at(3, 2) == Some(1.0));
+    assert!(local.path_counts(3).peek_at(0, 0) == Some(1.0));
+    assert!(local.path_counts(4).peek_at(0, 0) == Some(1.0));
+    // Closed walks of length 7 from 0 combine the 3-cycle and 4-cycle in either order
+    assert!(local.path_counts(7).peek_at(0, 0) == Some(2.0));
+}
+
+#[test]
+fn sparsemat_coalesce() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.set_keep_explicit_zeros(true);
+    local.insert_triplets(vec![(0, 0, 1.0), (0, 1, 0.0), (1, 1, 2.0), (2, 0, 0.0)]);
+    assert!(local.num_nonzero() == 4);
+
+    local.coalesce();
+    assert!(local.num_nonzero() == 2);
+    assert!(local.peek_at(0, 0) == Some(1.0));
+    assert!(local.peek_at(1, 1) == Some(2.0));
+    assert!(local.peek_at(0, 1).is_none());
+    assert!(local.peek_at(2, 0).is_none());
+    assert!(local.compressed_dataarray == vec![1.0, 2.0]);
+    assert!(local.compressed_rowarray == vec![0, 1, 2, 2]);
+}
+
+#[test]
+fn sparsemat_normalize_rows_l2() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 3.0), (0, 2, -4.0), (2, 1, 0.5)]);
+
+    local.normalize_rows_l2();
+    assert!(local.peek_at(0, 0) == Some(0.6));
+    assert!(local.peek_at(0, 2) == Some(-0.8));
+    assert!(local.peek_at(2, 1) == Some(1.0));
+    assert!(local.peek_at(1, 1).is_none());
+
+    for row in [0, 2] {
+        let sq_norm: f64 = (0..3)
+            .map(|col| local.peek_at(row, col).unwrap_or(0.0).powi(2))
+            .sum();
+        assert!((sq_norm.sqrt() - 1.0).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn sparsemat_to_dense() {
+    let mut local = SparseMatrix::empty_with_shape(2, 3);
+    local.insert_triplets(vec![(0, 2, 1.5), (1, 0, -2.0)]);
+    assert!(local.to_dense() == vec![vec![0.0, 0.0, 1.5], vec![-2.0, 0.0, 0.0]]);
+}
+
+#[cfg(feature = "nalgebra")]
+#[test]
+fn sparsemat_to_nalgebra() {
+    let mut local = SparseMatrix::empty_with_shape(2, 3);
+    local.insert_triplets(vec![(0, 2, 1.5), (1, 0, -2.0)]);
+
+    let dense = local.to_nalgebra();
+    assert!(dense.shape() == (2, 3));
+    assert!(dense[(0, 2)] == 1.5);
+    assert!(dense[(1, 0)] == -2.0);
+    assert!(dense[(1, 1)] == 0.0);
+}
+
+#[cfg(feature = "ndarray")]
+#[test]
+fn sparsemat_to_ndarray() {
+    let mut local = SparseMatrix::empty_with_shape(2, 3);
+    local.insert_triplets(vec![(0, 2, 1.5), (1, 0, -2.0)]);
+
+    let dense = local.to_ndarray();
+    assert!(dense.dim() == (2, 3));
+    assert!(dense[[0, 2]] == 1.5);
+    assert!(dense[[1, 0]] == -2.0);
+    assert!(dense[[1, 1]] == 0.0);
+}
+
+#[test]
+fn sparsemat_to_coo_string() {
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (2, 2, 50.0),
+        (1, 3, 40.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.5),
+    ]);
+
+    let dump = local.to_coo_string();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert!(lines.len() == 8);
+    assert!(lines[0] == "(0, 0) = 10");
+    assert!(lines[7] == "(3, 5) = 80.5");
+}
+
+#[test]
+fn sparsemat_prune() {
+    let mut local = SparseMatrix::empty_with_shape(2, 2);
+    local.set_keep_explicit_zeros(true);
+    local.insert_triplets(vec![(0, 0, 1.0), (0, 1, -1e-12), (1, 0, 0.0), (1, 1, 0.5)]);
+
+    local.prune(1e-9);
+    assert!(local.sorted_entries() == vec![((0, 0), 1.0), ((1, 1), 0.5)]);
+}
+
+#[test]
+fn sparsemat_with_transforms() {
+    let mut local = SparseMatrix::empty_with_shape(2, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (0, 2, 1e-12), (1, 1, -3.0)]);
+
+    let chained = local.with_scaled(2.0).with_pruned(1e-9).with_transpose();
+
+    let mut stepwise = local.clone();
+    stepwise.scale(2.0);
+    stepwise.prune(1e-9);
+    stepwise.transpose_inplace();
+
+    assert!(chained == stepwise);
+    assert!(chained.shape == (3, 2));
+    assert!(chained.sorted_entries() == vec![((0, 0), 2.0), ((1, 1), -6.0)]);
+    // The source is untouched
+    assert!(local.num_nonzero() == 3);
+}
+
+#[test]
+fn sparsemat_matvec() {
+    let mut local = SparseMatrix::empty_with_shape(2, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (0, 2, 2.0), (1, 1, -1.0)]);
+    assert!(local.matvec(&[1.0, 2.0, 3.0]) == vec![7.0, -2.0]);
+
+    local.explicitly_compress();
+    assert!(local.matvec(&[1.0, 2.0, 3.0]) == vec![7.0, -2.0]);
+}
+
+#[test]
+fn sparsemat_residual() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![
+        (0, 0, 4.0),
+        (0, 1, -1.0),
+        (1, 0, -1.0),
+        (1, 1, 4.0),
+        (1, 2, -1.0),
+        (2, 1, -1.0),
+        (2, 2, 4.0),
+    ]);
+    let x = vec![1.0, 2.0, 3.0];
+    let b = vec![2.0, 4.0, 10.0];
+
+    assert!(local.residual(&x, &b) == vec![0.0, 0.0, 0.0]);
+    assert!(local.residual_norm(&x, &b) < 1e-12);
+    assert!(local.residual(&[0.0, 0.0, 0.0], &b) == b);
+    assert!((local.residual_norm(&[0.0, 0.0, 0.0], &[3.0, 4.0, 0.0]) - 5.0).abs() < 1e-12);
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_residual_bad_length() {
+    let local = SparseMatrix::identity(3);
+    let _ = local.residual(&[1.0, 1.0, 1.0], &[1.0, 1.0]);
+}
+
+#[test]
+fn sparsemat_one_norm() {
+    let mut local = SparseMatrix::empty_with_shape(2, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (1, 0, -2.0), (0, 2, 2.5)]);
+    assert!(local.one_norm() == 3.0);
+}
+
+#[test]
+fn sparsemat_solve_dense() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 1, 2.0), (1, 0, 1.0), (1, 2, 1.0), (2, 2, 4.0)]);
+    let x = local.solve_dense(&[4.0, 4.0, 8.0]).unwrap();
+    assert!(local.residual_norm(&x, &[4.0, 4.0, 8.0]) < 1e-12);
+
+    let mut singular = SparseMatrix::empty_with_shape(2, 2);
+    singular.insert_triplets(vec![(0, 0, 1.0), (0, 1, 2.0), (1, 0, 2.0), (1, 1, 4.0)]);
+    assert!(singular.solve_dense(&[1.0, 1.0]).is_none());
+}
+
+#[test]
+fn sparsemat_condition_estimate() {
+    let mut diag = SparseMatrix::empty_with_shape(3, 3);
+    diag.insert_triplets(vec![(0, 0, 1.0), (1, 1, 2.0), (2, 2, 4.0)]);
+    let estimate = diag.condition_estimate().unwrap();
+    assert!((estimate - 4.0).abs() < 1e-12);
+
+    let mut ill = SparseMatrix::empty_with_shape(2, 2);
+    ill.insert_triplets(vec![
+        (0, 0, 1.0),
+        (0, 1, 1.0),
+        (1, 0, 1.0),
+        (1, 1, 1.0 + 1e-10),
+    ]);
+    assert!(ill.condition_estimate().unwrap() > 1e9);
+
+    // Badly scaled but not singular: solvable, and flagged by a huge estimate
+    let mut tiny = SparseMatrix::empty_with_shape(2, 2);
+    tiny.insert_triplets(vec![(0, 0, 1e-20), (1, 1, 1.0)]);
+    assert!(tiny.solve_dense(&[1e-20, 3.0]).unwrap() == vec![1.0, 3.0]);
+    assert!(tiny.condition_estimate().unwrap() >= 1e20);
+
+    let mut singular = SparseMatrix::empty_with_shape(2, 2);
+    singular.insert_triplets(vec![(0, 0, 1.0), (0, 1, 2.0), (1, 0, 2.0), (1, 1, 4.0)]);
+    assert!(singular.condition_estimate().is_none());
+}
+
+#[test]
+fn sparsemat_submatrix() {
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (2, 2, 50.0),
+        (1, 3, 40.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.0),
+    ]);
+
+    let block = local.submatrix(1..3, 1..4);
+    assert!(block.shape == (2, 3));
+    assert!(
+        block.sorted_entries()
+            == vec![
+                ((0, 0), 30.0),
+                ((0, 2), 40.0),
+                ((1, 1), 50.0),
+                ((1, 2), 60.0)
+            ]
+    );
+}
+
+#[test]
+fn sparsemat_set_submatrix() {
+    let mut local = SparseMatrix::empty_with_shape(4, 4);
+    local.insert_triplets(vec![
+        (0, 0, 1.0),
+        (1, 1, 2.0),
+        (1, 2, 3.0),
+        (2, 2, 4.0),
+        (3, 3, 5.0),
+    ]);
+
+    let mut block = SparseMatrix::empty_with_shape(2, 2);
+    block.insert_triplets(vec![(0, 0, 7.0), (1, 0, 8.0)]);
+    local.set_submatrix(1, 1, &block);
+
+    assert!(local.submatrix(1..3, 1..3) == block);
+    assert!(local.peek_at(1, 1) == Some(7.0));
+    assert!(local.peek_at(2, 1) == Some(8.0));
+    assert!(local.peek_at(1, 2).is_none());
+    assert!(local.peek_at(2, 2).is_none());
+    assert!(local.peek_at(0, 0) == Some(1.0));
+    assert!(local.peek_at(3, 3) == Some(5.0));
+    assert!(local.num_nonzero() == 4);
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_set_submatrix_oob() {
+    let mut local = SparseMatrix::empty_with_shape(4, 4);
+    local.set_submatrix(3, 3, &SparseMatrix::identity(2));
+}
+
+#[test]
+fn sparsemat_masked_frobenius() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 3.0), (0, 2, 4.0), (1, 1, 12.0), (2, 0, -5.0)]);
+    assert!(local.frobenius_norm() == (9.0f64 + 16.0 + 144.0 + 25.0).sqrt());
+
+    // Mask picks (0, 2) and (1, 1), plus an observed position with nothing stored
+    let mut mask = SparseMatrix::empty_with_shape(3, 3);
+    mask.insert_triplets(vec![(0, 2, 1.0), (1, 1, 1.0), (2, 2, 1.0)]);
+    assert!(local.masked_frobenius(&mask) == (16.0f64 + 144.0).sqrt());
+
+    let full = local.threshold_mask(|_| true);
+    assert!(local.masked_frobenius(&full) == local.frobenius_norm());
+    assert!(local.masked_frobenius(&SparseMatrix::empty_with_shape(3, 3)) == 0.0);
+}
+
+#[test]
+fn sparsemat_insert_zero_clears() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert(0, 0, 1.0);
+    local.insert(1, 1, 2.0);
+    assert!(local.num_nonzero() == 2);
+
+    local.insert(0, 0, 0.0);
+    assert!(local.num_nonzero() == 1);
+    assert!(local.peek_at(0, 0).is_none());
+
+    local.insert_triplets(vec![(1, 1, 0.0), (2, 2, 0.0)]);
+    assert!(local.num_nonzero() == 0);
+
+    local.set_keep_explicit_zeros(true);
+    local.insert(2, 2, 0.0);
+    assert!(local.num_nonzero() == 1);
+    assert!(local.peek_at(2, 2) == Some(0.0));
+}
+
+#[test]
+fn sparsemat_write_row_into() {
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (2, 2, 50.0),
+        (1, 3, 40.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.0),
+    ]);
+
+    let mut buf = vec![0.0; 6];
+    local.write_row_into(0, &mut buf);
+    assert!(buf == vec![10.0, 20.0, 0.0, 0.0, 0.0, 0.0]);
+    local.write_row_into(2, &mut buf);
+    assert!(buf == vec![0.0, 0.0, 50.0, 60.0, 70.0, 0.0]);
+
+    local.explicitly_compress();
+    local.write_row_into(1, &mut buf);
+    assert!(buf == vec![0.0, 30.0, 0.0, 40.0, 0.0, 0.0]);
+    local.write_row_into(3, &mut buf);
+    assert!(buf == vec![0.0, 0.0, 0.0, 0.0, 0.0, 80.0]);
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_write_row_into_bad_length() {
+    let local = SparseMatrix::identity(3);
+    let mut buf = vec![0.0; 2];
+    local.write_row_into(0, &mut buf);
+}
+
+#[test]
+fn sparsemat_merge_max() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (0, 1, 5.0), (2, 2, -2.0)]);
+    let mut local2 = SparseMatrix::empty_with_shape(3, 3);
+    local2.insert_triplets(vec![(0, 0, 3.0), (0, 1, 4.0), (1, 0, 7.0)]);
+
+    let merged = local.merge_max(&local2);
+    assert!(
+        merged.sorted_entries()
+            == vec![((0, 0), 3.0), ((0, 1), 5.0), ((1, 0), 7.0), ((2, 2), -2.0)]
+    );
+    assert!(merged == local2.merge_max(&local));
+}
+
+#[test]
+fn sparsemat_connected_components() {
+    // Block {0, 2, 4} linked one way only, block {1, 3} linked the other way
+    let mut local = SparseMatrix::empty_with_shape(5, 5);
+    local.insert_triplets(vec![
+        (0, 0, 2.0),
+        (0, 2, 1.0),
+        (4, 2, 1.0),
+        (3, 1, 1.0),
+        (1, 1, 2.0),
+    ]);
+
+    let labels = local.connected_components();
+    assert!(labels == vec![0, 1, 0, 1, 0]);
+
+    let mut isolated = SparseMatrix::identity(3);
+    isolated.insert(0, 1, 1.0);
+    assert!(isolated.connected_components() == vec![0, 0, 1]);
+}
+
+#[test]
+fn sparsemat_factor_lu() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![
+        (0, 1, 2.0),
+        (0, 2, 1.0),
+        (1, 0, 1.0),
+        (1, 2, 1.0),
+        (2, 0, 3.0),
+        (2, 2, 4.0),
+    ]);
+
+    let lu: LuFactorization = local.factor_lu().unwrap();
+    for b in [vec![4.0, 4.0, 8.0], vec![-1.0, 0.5, 2.0]] {
+        let x = lu.solve(&b);
+        let expected = local.solve_dense(&b).unwrap();
+        assert!(std::iter::zip(&x, &expected).all(|(a, e)| (a - e).abs() < 1e-12));
+        assert!(local.residual_norm(&x, &b) < 1e-12);
+
+        // Pivoting swaps rows here, so this also checks the permutation is undone
+        let xt = lu.solve_transpose(&b);
+        assert!(local
+            .matvec_transpose(&xt)
+            .iter()
+            .zip(&b)
+            .all(|(a, e)| (a - e).abs() < 1e-12));
+    }
+
+    assert!(SparseMatrix::empty_with_shape(2, 2).factor_lu().is_none());
+}
+
+#[test]
+fn sparsemat_matvec_banded() {
+    let n = 12;
+    let mut local = SparseMatrix::empty_with_shape(n, n);
+    for idx in 0..n {
+        local.insert(idx, idx, 2.0 + idx as f64);
+        if idx + 1 < n {
+            local.insert(idx, idx + 1, -1.0);
+            local.insert(idx + 1, idx, -0.5 * idx as f64 - 0.5);
+        }
+    }
+    let x: Vec<f64> = (0..n).map(|idx| (idx as f64 * 0.7).sin()).collect();
+
+    // The band is built by explicitly_compress and dropped with the CSR arrays
+    assert!(local.matvec_banded(&x).is_none());
+    local.explicitly_compress();
+    let banded = local.matvec_banded(&x).unwrap();
+    let general = local.matvec(&x);
+    assert!(std::iter::zip(&banded, &general).all(|(a, b)| (a - b).abs() < 1e-12));
+
+    // An in-band edit is picked up on the next compress
+    local.insert(5, 4, 9.0);
+    assert!(local.matvec_banded(&x).is_none());
+    local.explicitly_compress();
+    let banded = local.matvec_banded(&x).unwrap();
+    let general = local.matvec(&x);
+    assert!(std::iter::zip(&banded, &general).all(|(a, b)| (a - b).abs() < 1e-12));
+
+    // A far off-diagonal entry widens the band past the cutoff
+    local.insert(0, n - 1, 1.0);
+    local.explicitly_compress();
+    assert!(local.matvec_banded(&x).is_none());
+}
+
+#[test]
+fn sparsemat_update_at() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert(1, 2, 3.0);
+
+    local.update_at(1, 2, |v| v * 2.0);
+    assert!(local.peek_at(1, 2) == Some(6.0));
+
+    local.update_at(0, 0, |v| v + 1.5);
+    assert!(local.peek_at(0, 0) == Some(1.5));
+
+    local.update_at(1, 2, |v| v - 6.0);
+    assert!(local.peek_at(1, 2).is_none());
+    assert!(local.num_nonzero() == 1);
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_update_at_oob() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.update_at(3, 0, |v| v + 1.0);
+}
+
+#[test]
+fn sparsemat_explicit_zeros() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.set_keep_explicit_zeros(true);
+    local.insert_triplets(vec![(0, 0, 1.0), (0, 1, 0.0), (2, 2, 0.0)]);
+    assert!(local.explicit_zeros() == 2);
+    assert!(local.num_nonzero() == 3);
+
+    local.prune(0.0);
+    assert!(local.explicit_zeros() == 0);
+}
+
+#[test]
+fn sparsemat_matvec_transpose() {
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (2, 2, 50.0),
+        (1, 3, 40.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.0),
+    ]);
+    let x = vec![1.0, -2.0, 0.5, 3.0];
+
+    assert!(local.matvec_transpose(&x) == local.create_transpose().matvec(&x));
+    assert!(local.matvec_transpose(&x) == vec![10.0, -40.0, 25.0, -50.0, 35.0, 240.0]);
+}
+
+#[test]
+fn sparsemat_solve_bicgstab() {
+    let mut local = SparseMatrix::empty_with_shape(4, 4);
+    local.insert_triplets(vec![
+        (0, 0, 4.0),
+        (0, 1, 1.0),
+        (1, 0, -2.0),
+        (1, 1, 5.0),
+        (1, 3, 1.0),
+        (2, 1, 3.0),
+        (2, 2, 6.0),
+        (3, 0, 1.0),
+        (3, 2, -1.0),
+        (3, 3, 3.0),
+    ]);
+    assert!(!local.is_symmetric());
+    let b = vec![1.0, 2.0, 3.0, 4.0];
+
+    let x = local.solve_bicgstab(&b, 100, 1e-10).unwrap();
+    assert!(local.residual_norm(&x, &b) < 1e-8);
+
+    let spd = SparseMatrix::random_spd(30, 0.1, 5);
+    let b: Vec<f64> = (0..30).map(|idx| idx as f64).collect();
+    let x = spd.solve_bicgstab(&b, 200, 1e-10).unwrap();
+    assert!(spd.residual_norm(&x, &b) < 1e-7);
+
+    assert!(local.solve_bicgstab(&b[..4], 0, 1e-10).is_none());
+}
+
+#[test]
+fn sparsemat_diagonal() {
+    let mut local = SparseMatrix::empty_with_shape(3, 4);
+    local.insert_triplets(vec![(0, 0, 1.0), (2, 2, 3.0), (1, 3, 9.0)]);
+    assert!(local.diagonal() == vec![1.0, 0.0, 3.0]);
+}
+
+#[test]
+fn sparsemat_solve_cg() {
+    let spd = SparseMatrix::random_spd(30, 0.1, 9);
+    let b: Vec<f64> = (0..30).map(|idx| 1.0 - idx as f64 * 0.1).collect();
+    let x = spd.solve_cg(&b, 100, 1e-10, None).unwrap();
+    assert!(spd.residual_norm(&x, &b) < 1e-8);
+}
+
+#[test]
+fn sparsemat_jacobi_preconditioner() {
+    // D S D with S a well-conditioned tridiagonal and D spanning several orders of magnitude
+    let n = 40;
+    let scales: Vec<f64> = (0..n)
+        .map(|idx| 10f64.powf(((idx * 7) % 13) as f64 / 4.0))
+        .collect();
+    let mut local = SparseMatrix::empty_with_shape(n as u64, n as u64);
+    for idx in 0..n {
+        local.insert(idx as u64, idx as u64, 2.0 * scales[idx] * scales[idx]);
+        if idx + 1 < n {
+            let off = -0.5 * scales[idx] * scales[idx + 1];
+            local.insert(idx as u64, idx as u64 + 1, off);
+            local.insert(idx as u64 + 1, idx as u64, off);
+        }
+    }
+    let b = vec![1.0; n];
+
+    let jacobi = JacobiPreconditioner::new(&local).unwrap();
+    assert!(jacobi.apply(&b)[0] == 0.5);
+
+    let max_iter = 30;
+    let x = local.solve_cg(&b, max_iter, 1e-10, Some(&jacobi)).unwrap();
+    assert!(local.residual_norm(&x, &b) < 1e-8);
+    assert!(local.solve_cg(&b, max_iter, 1e-10, None).is_none());
+
+    let mut zero_diag = SparseMatrix::identity(3);
+    zero_diag.clear_at(1, 1);
+    assert!(JacobiPreconditioner::new(&zero_diag).is_none());
+}
+
+#[test]
+fn sparsemat_solve_reported() {
+    let spd = SparseMatrix::random_spd(30, 0.1, 9);
+    let b: Vec<f64> = (0..30).map(|idx| 1.0 - idx as f64 * 0.1).collect();
+    let tol = 1e-10;
+    let b_norm = b.iter().map(|v| v * v).sum::<f64>().sqrt();
+
+    let (x, report): (Vec<f64>, SolveReport) = spd.solve_cg_reported(&b, 100, tol, None);
+    assert!(report.converged);
+    assert!(report.iterations > 0);
+    assert!(report.final_residual <= tol * b_norm);
+    assert!(spd.residual_norm(&x, &b) < 1e-8);
+
+    let (x, report) = spd.solve_bicgstab_reported(&b, 100, tol);
+    assert!(report.converged);
+    assert!(report.iterations > 0);
+    assert!(report.final_residual <= tol * b_norm);
+    assert!(spd.residual_norm(&x, &b) < 1e-8);
+
+    let (_, report) = spd.solve_cg_reported(&b, 2, tol, None);
+    assert!(!report.converged);
+    assert!(report.iterations == 2);
+    assert!(report.final_residual > tol * b_norm);
+}
+
+#[test]
+fn sparsemat_shape_accessors() {
+    let local = SparseMatrix::empty_with_shape(4, 6);
+    assert!(local.shape() == (4, 6));
+    assert!(local.rows() == 4);
+    assert!(local.cols() == 6);
+    assert!(local.create_transpose().shape() == (6, 4));
+}
+
+#[test]
+fn sparsemat_subtraction() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 10.0), (0, 1, 20.0), (1, 1, 30.0), (2, 2, 50.0)]);
+    let local2 = local.create_transpose();
+
+    let local3 = &local - &local2;
+    assert!(local3.peek_at(0, 0).is_none());
+    assert!(local3.peek_at(0, 1) == Some(20.0));
+    assert!(local3.peek_at(1, 0) == Some(-20.0));
+    assert!(local3.num_nonzero() == 2);
+}
+
+#[test]
+fn sparsemat_zip_with() {
+    let a = SparseMatrix::random((6, 5), 0.4, 21);
+    let mut b = SparseMatrix::random((6, 5), 0.4, 22);
+    for ((row, col), val) in a.sorted_entries().into_iter().take(3) {
+        b.insert(row, col, val);
+    }
+
+    let diff = a.zip_with(&b, |x, y| x - y);
+    assert!(diff == &a - &b);
+    assert!(a.zip_with(&b, |x, y| x + y) == &a + &b);
+
+    let product = a.zip_with(&b, |x, y| x * y);
+    assert!(product.num_nonzero() <= a.num_nonzero().min(b.num_nonzero()));
+}
+
+#[test]
+fn sparsemat_validate() {
+    let mut local = SparseMatrix::empty_with_shape(4, 4);
+    local.insert_triplets(vec![(0, 0, 1.0), (3, 1, 2.0)]);
+    assert!(local.validate().is_ok());
+
+    // Shrinking the public shape leaves (3, 1) outside it
+    local.shape = (3, 3);
+    assert!(
+        local.validate()
+            == Err(SparseError::IndexOutOfBounds {
+                row: 3,
+                col: 1,
+                shape: (3, 3)
+            })
+    );
+}
+
+#[test]
+fn sparsemat_max_abs() {
+    assert!(SparseMatrix::empty_with_shape(3, 3).max_abs() == 0.0);
+
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (1, 2, -250.0), (2, 1, 40.0)]);
+    assert!(local.max_abs() == 250.0);
+}
+
+#[test]
+fn sparsemat_normalize() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 2.0), (1, 2, -8.0), (2, 1, 4.0)]);
+
+    local.normalize();
+    assert!(local.max_abs() == 1.0);
+    assert!(local.peek_at(1, 2) == Some(-1.0));
+    assert!(local.peek_at(0, 0) == Some(0.25));
+
+    let mut empty = SparseMatrix::empty_with_shape(2, 2);
+    empty.normalize();
+    assert!(empty.num_nonzero() == 0);
+}
+
+#[test]
+fn sparsemat_count_where() {
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (2, 2, 50.0),
+        (1, 3, 40.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.0),
+    ]);
+
+    assert!(local.count_where(|v| v > 40.0) == 4);
+    assert!(local.count_where(|v| v < 0.0) == 0);
+}
+
+#[test]
+fn sparsemat_mul_dense() {
+    let a = SparseMatrix::random((5, 7), 0.5, 31);
+    let b = SparseMatrix::random((7, 4), 0.5, 32);
+
+    let dense = a.mul_dense(&b);
+    assert!(dense.len() == 5 && dense[0].len() == 4);
+    assert!(dense == (&a * &b).to_dense());
+}
+
+#[test]
+fn sparsemat_eq_ignoring_explicit_zeros() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (2, 1, -4.0)]);
+
+    let mut local2 = SparseMatrix::empty_with_shape(3, 3);
+    local2.set_keep_explicit_zeros(true);
+    local2.insert_triplets(vec![(0, 0, 1.0), (1, 1, 0.0), (2, 1, -4.0)]);
+
+    assert!(local != local2);
+    assert!(local.eq_ignoring_explicit_zeros(&local2));
+    assert!(local2.eq_ignoring_explicit_zeros(&local));
+
+    local2.insert(1, 2, 3.0);
+    assert!(!local.eq_ignoring_explicit_zeros(&local2));
+    assert!(!local.eq_ignoring_explicit_zeros(&SparseMatrix::empty_with_shape(3, 4)));
+}
+
+#[test]
+fn sparsemat_scale_to_trace() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 2.0), (1, 1, 3.0), (2, 2, 5.0), (0, 2, 1.0)]);
+
+    let factor = local.scale_to_trace(1.0).unwrap();
+    assert!(factor == 0.1);
+    assert!((local.trace() - 1.0).abs() < 1e-12);
+    assert!(local.peek_at(0, 2) == Some(0.1));
+
+    let mut traceless = SparseMatrix::empty_with_shape(2, 2);
+    traceless.insert_triplets(vec![(0, 0, 1.0), (1, 1, -1.0)]);
+    assert!(traceless.scale_to_trace(1.0).is_none());
+    assert!(traceless.peek_at(0, 0) == Some(1.0));
+}
+
+#[test]
+fn sparsemat_read_matrix_market_from() {
+    let input: &[u8] = b"%%MatrixMarket matrix coordinate real general
+% a comment
+3 4 3
+1 1 1.5
+2 4 -2.0
+3 2 7
+";
+    let local = SparseMatrix::read_matrix_market_from(input).unwrap();
+    assert!(local.shape == (3, 4));
+    assert!(local.sorted_entries() == vec![((0, 0), 1.5), ((1, 3), -2.0), ((2, 1), 7.0)]);
+
+    let symmetric: &[u8] = b"%%MatrixMarket matrix coordinate pattern symmetric
+2 2 2
+1 1
+2 1
+";
+    let local = SparseMatrix::read_matrix_market_from(symmetric).unwrap();
+    assert!(local.sorted_entries() == vec![((0, 0), 1.0), ((0, 1), 1.0), ((1, 0), 1.0)]);
+}
+
+#[test]
+fn sparsemat_read_matrix_market_errors() {
+    let oob: &[u8] = b"%%MatrixMarket matrix coordinate real general\n2 2 1\n3 1 1.0\n";
+    assert!(
+        SparseMatrix::read_matrix_market_from(oob).err()
+            == Some(SparseError::IndexOutOfBounds {
+                row: 3,
+                col: 1,
+                shape: (2, 2)
+            })
+    );
+
+    let bad_value: &[u8] = b"%%MatrixMarket matrix coordinate real general\n2 2 1\n1 1 abc\n";
+    assert!(matches!(
+        SparseMatrix::read_matrix_market_from(bad_value),
+        Err(SparseError::Parse { line: 3, .. })
+    ));
+
+    let short: &[u8] = b"%%MatrixMarket matrix coordinate real general\n2 2 2\n1 1 1.0\n";
+    assert!(matches!(
+        SparseMatrix::read_matrix_market_from(short),
+        Err(SparseError::Parse { line: 3, .. })
+    ));
+
+    let no_size: &[u8] = b"%%MatrixMarket matrix coordinate real general\n% comment\n\n";
+    assert!(matches!(
+        SparseMatrix::read_matrix_market_from(no_size),
+        Err(SparseError::Parse { line: 3, .. })
+    ));
+
+    assert!(matches!(
+        SparseMatrix::read_matrix_market("does/not/exist.mtx"),
+        Err(SparseError::Io(_))
+    ));
+}
+
+#[test]
+fn sparsemat_density() {
+    assert!(SparseMatrix::new().density() == 0.0);
+    assert!(SparseMatrix::identity(4).density() == 0.25);
+}
+
+#[test]
+fn sparsemat_recommended_format() {
+    assert!(SparseMatrix::identity(100).recommended_format() == StorageHint::Csr);
+
+    let mut full = SparseMatrix::empty_with_shape(4, 4);
+    for row in 0..4 {
+        for col in 0..4 {
+            if row != col || row == 0 {
+                full.insert(row, col, 1.0);
+            }
+        }
+    }
+    assert!(full.recommended_format() == StorageHint::Dense);
+
+    let mut nearly_empty = SparseMatrix::empty_with_shape(100, 100);
+    nearly_empty.insert(3, 7, 1.0);
+    assert!(nearly_empty.recommended_format() == StorageHint::Coo);
+}
+
+#[test]
+fn sparsemat_permute_symmetric() {
+    let local = SparseMatrix::random_spd(8, 0.3, 13);
+    let perm = vec![3, 7, 0, 5, 1, 6, 2, 4];
+
+    let permuted = local.permute_symmetric(&perm);
+    assert!(permuted.is_symmetric());
+    assert!(permuted == local.permute_rows(&perm).permute_cols(&perm));
+    assert!(permuted.peek_at(0, 1) == local.peek_at(3, 7));
+    assert!((permuted.trace() - local.trace()).abs() < 1e-12);
+}
+
+#[test]
+fn sparsemat_try_compress_non_finite() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (2, 1, 2.0)]);
+    assert!(local.try_compress().is_ok());
+    assert!(local.compressed_dataarray == vec![1.0, 2.0]);
+
+    local.insert(1, 2, f64::NAN);
+    let err = local.try_compress().unwrap_err();
+    assert!(err == SparseError::NonFinite { row: 1, col: 2 });
+    assert!(err.to_string() == "non-finite value stored at (1, 2)");
+    // The previous compressed arrays are left as they were
+    assert!(local.compressed_dataarray == vec![1.0, 2.0]);
+}
+
+#[test]
+fn sparsemat_solvers_refuse_non_finite() {
+    let mut local = SparseMatrix::identity(3);
+    let (x, report) = local.solve_cg_reported(&[1.0, f64::INFINITY, 0.0], 10, 1e-10, None);
+    assert!(!report.converged && report.iterations == 0);
+    assert!(x == vec![0.0; 3]);
+
+    local.insert(1, 1, f64::NAN);
+    let (x, report) = local.solve_cg_reported(&[1.0, 2.0, 2.0], 10, 1e-10, None);
+    assert!(
+        report
+            == SolveReport {
+                iterations: 0,
+                final_residual: 3.0,
+                converged: false
+            }
+    );
+    assert!(x == vec![0.0; 3]);
+
+    let (x, report) = local.solve_bicgstab_reported(&[1.0, 2.0, 2.0], 10, 1e-10);
+    assert!(!report.converged && report.iterations == 0);
+    assert!(x.iter().all(|v| v.is_finite()));
+    assert!(local.solve_cg(&[1.0, 2.0, 2.0], 10, 1e-10, None).is_none());
+}
+
+#[test]
+fn sparsemat_empty_default() {
+    #[derive(Default)]
+    struct Holder {
+        matrix: SparseMatrix,
+    }
+
+    let local = SparseMatrix::default();
+    assert!(local.num_nonzero() == 0);
+    assert!(local.shape() == (0, 0));
+    assert!(SparseMatrix::empty() == local);
+    assert!(Holder::default().matrix == SparseMatrix::new());
+}
+
+#[test]
+fn sparsemat_row_iter_range() {
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (2, 2, 50.0),
+        (1, 3, 40.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.0),
+    ]);
+    local.explicitly_compress();
+
+    let rows: Vec<Vec<f64>> = local.row_iter_range(1..3).collect();
+    assert!(rows.len() == 2);
+    assert!(rows[0] == vec![0.0, 30.0, 0.0, 40.0, 0.0, 0.0]);
+    assert!(rows[1] == vec![0.0, 0.0, 50.0, 60.0, 70.0, 0.0]);
+
+    assert!(local.row_iter_range(2..2).count() == 0);
+    assert!(local.row_iter().count() == 4);
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_row_iter_range_oob() {
+    let local = SparseMatrix::identity(3);
+    let _ = local.row_iter_range(1..4);
+}
+
+#[test]
+fn sparsemat_cholesky() {
+    let local = SparseMatrix::random_spd(10, 0.2, 17);
+    let l = local.cholesky().unwrap();
+    let rebuilt = &l * &l.create_transpose();
+    assert!((&rebuilt - &local).max_abs() < 1e-12);
+    assert!(l.sorted_entries().iter().all(|((row, col), _)| col <= row));
+
+    let mut indefinite = SparseMatrix::identity(2);
+    indefinite.insert(1, 1, -1.0);
+    assert!(indefinite.cholesky().is_none());
+}
+
+#[test]
+fn sparsemat_min_degree_permutation() {
+    // Arrow pattern: node 0 coupled to everything, so eliminating it first fills L completely
+    let n = 8;
+    let mut local = SparseMatrix::empty_with_shape(n, n);
+    for idx in 0..n {
+        local.insert(idx, idx, n as f64);
+        if idx > 0 {
+            local.insert(0, idx, 1.0);
+            local.insert(idx, 0, 1.0);
+        }
+    }
+
+    let perm = local.min_degree_permutation();
+    let mut sorted = perm.clone();
+    sorted.sort();
+    assert!(sorted == (0..n).collect::<Vec<u64>>());
+    // The hub waits until only one leaf is left beside it
+    assert!(!perm[..n as usize - 2].contains(&0));
+
+    let natural_fill = local.cholesky().unwrap().num_nonzero();
+    let reordered_fill = local
+        .permute_symmetric(&perm)
+        .cholesky()
+        .unwrap()
+        .num_nonzero();
+    assert!(natural_fill == n * (n + 1) / 2);
+    assert!(reordered_fill == 2 * n - 1);
+    assert!(reordered_fill < natural_fill);
+}
+
+#[test]
+fn sparsemat_rank1_update() {
+    let mut local = SparseMatrix::empty_with_shape(2, 3);
+    local.insert_triplets(vec![(0, 0, 1.0), (1, 2, 6.0)]);
+
+    local.rank1_update(2.0, &[1.0, -1.0], &[0.0, 1.0, 3.0]);
+    assert!(
+        local.sorted_entries() == vec![((0, 0), 1.0), ((0, 1), 2.0), ((0, 2), 6.0), ((1, 1), -2.0)]
+    );
+}
+
+#[test]
+fn sparsemat_rank_k_update() {
+    let base = SparseMatrix::random((5, 4), 0.3, 41);
+    let u_cols = vec![
+        vec![1.0, 0.0, -2.0, 0.5, 0.0],
+        vec![0.0, 3.0, 1.0, 0.0, -1.0],
+        vec![2.0, 0.0, 0.0, 0.0, 1.0],
+    ];
+    let v_cols = vec![
+        vec![0.5, 0.0, 1.0, -1.0],
+        vec![1.0, 2.0, 0.0, 0.0],
+        vec![0.0, 0.0, -0.5, 4.0],
+    ];
+
+    let mut batched = base.clone();
+    batched.rank_k_update(-1.5, &u_cols, &v_cols);
+
+    let mut repeated = base.clone();
+    for (u, v) in std::iter::zip(&u_cols, &v_cols) {
+        repeated.rank1_update(-1.5, u, v);
+    }
+
+    assert!((&batched - &repeated).max_abs() < 1e-12);
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_rank_k_update_mismatched_counts() {
+    let mut local = SparseMatrix::empty_with_shape(2, 2);
+    local.rank_k_update(1.0, &[vec![1.0, 1.0]], &[]);
+}
+
+#[test]
+fn sparsemat_to_undirected() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 1, 2.0), (1, 0, 5.0), (1, 2, 1.5), (2, 2, 4.0)]);
+    assert!(!local.is_symmetric());
+
+    let max = local.to_undirected(EdgeCombine::Max);
+    assert!(max.is_symmetric());
+    assert!(max.peek_at(0, 1) == Some(5.0));
+    assert!(max.peek_at(2, 1) == Some(1.5));
+    assert!(max.peek_at(2, 2) == Some(4.0));
+
+    let sum = local.to_undirected(EdgeCombine::Sum);
+    assert!(sum.is_symmetric());
+    assert!(sum.peek_at(1, 0) == Some(7.0));
+    assert!(sum.peek_at(1, 2) == Some(1.5));
+    assert!(sum.peek_at(2, 2) == Some(4.0));
+    assert!(sum.num_nonzero() == 5);
+}
+
+#[test]
+fn sparsemat_csv_triplets_roundtrip() {
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (2, 2, 50.0),
+        (1, 3, 40.0),
+        (2, 3, -60.5),
+        (2, 4, 70.0),
+        (3, 5, 1e-7),
+    ]);
+
+    for header in [true, false] {
+        for base in [IndexBase::Zero, IndexBase::One] {
+            let mut buffer: Vec<u8> = vec![];
+            local.write_csv_triplets(&mut buffer, header, base).unwrap();
+            assert!(buffer.starts_with(b"row,col,value\n") == header);
+
+            let parsed = SparseMatrix::read_csv_triplets(buffer.as_slice(), (4, 6), base).unwrap();
+            assert!(parsed == local);
+        }
+    }
+
+    // 1-based spreadsheet export: (4, 6) is the last valid cell
+    let mut buffer: Vec<u8> = vec![];
+    local
+        .write_csv_triplets(&mut buffer, false, IndexBase::One)
+        .unwrap();
+    assert!(buffer.starts_with(b"1,1,10\n"));
+    assert!(buffer.ends_with(b"4,6,0.0000001\n"));
+}
+
+#[test]
+fn sparsemat_csv_triplets_errors() {
+    let input: &[u8] = b"row,col,value\n0,0,1.0\n5,1,2.0\n";
+    assert!(
+        SparseMatrix::read_csv_triplets(input, (3, 3), IndexBase::Zero).err()
+            == Some(SparseError::IndexOutOfBounds {
+                row: 5,
+                col: 1,
+                shape: (3, 3)
+            })
+    );
+
+    // Index 0 doesn't exist in 1-based data
+    let input: &[u8] = b"1,1,1.0\n0,2,2.0\n";
+    assert!(
+        SparseMatrix::read_csv_triplets(input, (3, 3), IndexBase::One).err()
+            == Some(SparseError::IndexOutOfBounds {
+                row: 0,
+                col: 2,
+                shape: (3, 3)
+            })
+    );
+
+    // The header is recognized on the first non-empty line, not only on line 1
+    let input: &[u8] = b"\n  \nrow,col,value\n2,1,3.0\n";
+    let parsed = SparseMatrix::read_csv_triplets(input, (3, 3), IndexBase::Zero).unwrap();
+    assert!(parsed.peek_at(2, 1) == Some(3.0) && parsed.num_nonzero() == 1);
+
+    // A malformed first data row is an error, not a header
+    let input: &[u8] = b"x1,0,1.0\n1,1,2.0\n";
+    assert!(matches!(
+        SparseMatrix::read_csv_triplets(input, (3, 3), IndexBase::Zero),
+        Err(SparseError::Parse { line: 1, .. })
+    ));
+
+    // Only one header: a second non-numeric line is an error
+    let input: &[u8] = b"row,col,value\nrow,col,value\n";
+    assert!(matches!(
+        SparseMatrix::read_csv_triplets(input, (3, 3), IndexBase::Zero),
+        Err(SparseError::Parse { line: 2, .. })
+    ));
+
+    let input: &[u8] = b"0,0,1.0\n1,x,2.0\n";
+    assert!(matches!(
+        SparseMatrix::read_csv_triplets(input, (3, 3), IndexBase::Zero),
+        Err(SparseError::Parse { line: 2, .. })
+    ));
+}
+
+#[test]
+fn sparsemat_energy_norm() {
+    let mut local = SparseMatrix::empty_with_shape(2, 2);
+    local.insert_triplets(vec![(0, 0, 2.0), (0, 1, 1.0), (1, 0, 1.0), (1, 1, 3.0)]);
+
+    // x^T A x = 2 + 2 + 3 = 7 at x = [1, 1]
+    assert!(local.energy_norm(&[1.0, 1.0]) == 7f64.sqrt());
+    assert!(local.energy_norm(&[0.0, 2.0]) == 12f64.sqrt());
+
+    let mut indefinite = SparseMatrix::identity(2);
+    indefinite.insert(1, 1, -4.0);
+    assert!(indefinite.energy_norm(&[1.0, 1.0]).is_nan());
+}
+
+#[test]
+fn sparsemat_dense_window_iter() {
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (2, 2, 50.0),
+        (1, 3, 40.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.0),
+    ]);
+
+    let window: Vec<(u64, u64, f64)> = local.dense_window_iter(1..3, 2..4).collect();
+    assert!(window == vec![(1, 2, 0.0), (1, 3, 40.0), (2, 2, 50.0), (2, 3, 60.0)]);
+    assert!(local.dense_window_iter(0..4, 0..6).count() == 24);
+    assert!(local.dense_window_iter(1..1, 0..6).count() == 0);
+}
+
+#[test]
+fn sparsemat_is_zero() {
+    assert!(SparseMatrix::empty_with_shape(3, 3).is_zero());
+
+    let mut zeros = SparseMatrix::empty_with_shape(3, 3);
+    zeros.set_keep_explicit_zeros(true);
+    zeros.insert_triplets(vec![(0, 0, 0.0), (2, 1, 0.0)]);
+    assert!(zeros.num_nonzero() == 2);
+    assert!(zeros.is_zero());
+
+    zeros.insert(1, 1, -0.5);
+    assert!(!zeros.is_zero());
+}
+
+#[test]
+fn sparsemat_add_sub_with_empty() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![(0, 0, 10.0), (0, 1, 20.0), (2, 2, -5.0)]);
+    let empty = SparseMatrix::empty_with_shape(3, 3);
+
+    let sum = &local + &empty;
+    assert!(sum == local);
+    assert!(sum.num_nonzero() == local.num_nonzero());
+    assert!(&empty + &local == local);
+
+    assert!(&local - &empty == local);
+
+    let negated = &empty - &local;
+    assert!(negated.sorted_entries() == vec![((0, 0), -10.0), ((0, 1), -20.0), ((2, 2), 5.0)]);
+}
+
+#[test]
+fn sparsemat_add_sub_with_explicit_zeros() {
+    let mut zeros = SparseMatrix::empty_with_shape(2, 2);
+    zeros.set_keep_explicit_zeros(true);
+    zeros.insert(0, 0, 0.0);
+    let mut other = SparseMatrix::empty_with_shape(2, 2);
+    other.insert(1, 1, 3.0);
+
+    // A zero-valued left side that keeps explicit zeros must not be swapped out for other
+    for result in [&zeros + &other, &zeros - &other] {
+        assert!(result.keeps_explicit_zeros());
+        assert!(result.num_nonzero() == 2);
+        assert!(result.peek_at(0, 0) == Some(0.0));
+    }
+    assert!((&zeros - &other).peek_at(1, 1) == Some(-3.0));
+
+    // Explicit zeros on the right are still inserted into a left side that keeps them
+    let mut kept = SparseMatrix::empty_with_shape(2, 2);
+    kept.set_keep_explicit_zeros(true);
+    kept.insert(1, 0, 1.0);
+    let sum = &kept + &zeros;
+    assert!(sum.num_nonzero() == 2);
+    assert!(sum.peek_at(0, 0) == Some(0.0));
+}
+
+#[test]
+fn sparsemat_extend() {
+    let mut source = SparseMatrix::empty_with_shape(4, 6);
+    source.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (2, 2, 50.0),
+        (1, 3, 40.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.0),
+    ]);
+    assert!(source.iter_nonzero().count() == 8);
+
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert(0, 0, -1.0);
+    local.extend(source.iter_nonzero().filter(|(row, _, _)| *row < 2));
+    assert!(
+        local.sorted_entries()
+            == vec![
+                ((0, 0), 10.0),
+                ((0, 1), 20.0),
+                ((1, 1), 30.0),
+                ((1, 3), 40.0)
+            ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_extend_oob() {
+    let mut local = SparseMatrix::empty_with_shape(2, 2);
+    local.extend(vec![(0, 0, 1.0), (2, 0, 1.0)]);
+}
+
+#[test]
+fn sparsemat_l1_norm_entrywise() {
+    let mut local = SparseMatrix::empty_with_shape(2, 2);
+    local.insert_triplets(vec![(0, 1, -3.0), (1, 1, 4.0)]);
+    assert!(local.l1_norm_entrywise() == 7.0);
+    assert!(local.one_norm() == 7.0);
+
+    local.insert(0, 0, 1.0);
+    assert!(local.l1_norm_entrywise() == 8.0);
+    assert!(local.one_norm() == 7.0);
+}
+
+#[test]
+fn sparsemat_from_map() {
+    let mut values = HashMap::new();
+    values.insert((0, 1), 2.0);
+    values.insert((2, 0), -1.0);
+
+    let local = SparseMatrix::from_map((3, 2), values.clone()).unwrap();
+    assert!(local.shape == (3, 2));
+    assert!(local.peek_at(0, 1) == Some(2.0));
+    assert!(local.peek_at(2, 0) == Some(-1.0));
+    assert!(local.num_nonzero() == 2);
+
+    // Zeros in the map don't survive as explicit entries
+    let mut with_zero = values.clone();
+    with_zero.insert((1, 1), 0.0);
+    let local = SparseMatrix::from_map((3, 2), with_zero).unwrap();
+    assert!(local.num_nonzero() == 2);
+    assert!(local.peek_at(1, 1).is_none());
+    assert!(local.explicit_zeros() == 0);
+
+    values.insert((1, 2), 5.0);
+    assert!(
+        SparseMatrix::from_map((3, 2), values).err()
+            == Some(SparseError::IndexOutOfBounds {
+                row: 1,
+                col: 2,
+                shape: (3, 2)
+            })
+    );
+}
+
+#[test]
+fn sparsemat_nnz_compressed() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.set_keep_explicit_zeros(true);
+    local.insert_triplets(vec![(0, 0, 1.0), (1, 1, 0.0), (2, 0, 3.0)]);
+
+    assert!(local.num_nonzero() == 3);
+    assert!(local.nnz_compressed() == 2);
+
+    local.explicitly_compress();
+    assert!(local.nnz_compressed() == 2);
+    assert!(local.compressed_dataarray == vec![1.0, 3.0]);
+    assert!(local.compressed_rowarray == vec![0, 1, 1, 2]);
+}
+
+#[test]
+fn sparsemat_display_truncated() {
+    let local = SparseMatrix::identity(100);
+    let out = local.display_truncated(5, 4);
+    println!("{}", out);
+
+    assert!(out.contains('…'));
+    assert!(out.lines().count() == 8);
+    assert!(out.len() < 300);
+    assert!(out.lines().nth(1).unwrap() == "\t|   1.00,   0.00,   0.00,   0.00, … |");
+
+    let mut small = SparseMatrix::empty_with_shape(2, 2);
+    small.insert(0, 1, 3.0);
+    small.explicitly_compress();
+    let out = small.display_truncated(5, 5);
+    assert!(!out.contains('…'));
+    assert!(out == format!("{}", small));
+}
+
+#[test]
+fn sparsemat_transpose_view() {
+    let local = SparseMatrix::random((5, 3), 0.5, 51);
+    let view: TransposeView = local.t();
+    assert!(view.shape() == (3, 5));
+
+    for row in 0..5 {
+        for col in 0..3 {
+            assert!(view.peek_at(col, row) == local.peek_at(row, col));
+        }
+    }
+
+    let x = vec![1.0, -1.0, 2.0, 0.5, 3.0];
+    assert!(view.matvec(&x) == local.create_transpose().matvec(&x));
+}
+
+#[test]
+fn sparsemat_magnitude_histogram() {
+    let mut local = SparseMatrix::empty_with_shape(3, 3);
+    local.insert_triplets(vec![
+        (0, 0, 1e-6),
+        (0, 1, -2e-6),
+        (1, 1, 1e-3),
+        (1, 2, 5e-2),
+        (2, 0, -1.0),
+        (2, 2, 1.0),
+    ]);
+    assert!(local.min_abs() == 1e-6);
+
+    // Bins cover decades [1e-6, 1e-4), [1e-4, 1e-2), [1e-2, 1]
+    let counts = local.magnitude_histogram(3);
+    assert!(counts == vec![2, 1, 3]);
+    assert!(counts.iter().sum::<u64>() == local.num_nonzero());
+
+    assert!(SparseMatrix::empty_with_shape(2, 2).magnitude_histogram(4) == vec![0, 0, 0, 0]);
+    assert!(SparseMatrix::identity(3).magnitude_histogram(4) == vec![3, 0, 0, 0]);
+
+    // Non-finite values are skipped instead of landing in the smallest bin
+    for bad in [f64::INFINITY, f64::NAN] {
+        let mut local = SparseMatrix::empty_with_shape(2, 2);
+        local.insert_triplets(vec![(0, 0, bad), (0, 1, 2.0), (1, 1, 200.0)]);
+        assert!(local.magnitude_histogram(3) == vec![1, 0, 1]);
+    }
+}
+
+#[test]
+fn sparsemat_lerp() {
+    let a = SparseMatrix::random((4, 4), 0.4, 61);
+    let b = SparseMatrix::random((4, 4), 0.4, 62);
+
+    assert!(a.lerp(&b, 0.0) == a);
+    assert!(a.lerp(&b, 1.0) == b);
+
+    let mut c = SparseMatrix::empty_with_shape(2, 2);
+    c.insert_triplets(vec![(0, 0, 2.0), (1, 1, 1.0)]);
+    let mut d = SparseMatrix::empty_with_shape(2, 2);
+    d.insert_triplets(vec![(0, 0, 4.0), (1, 1, -1.0), (0, 1, 8.0)]);
+    let mid = c.lerp(&d, 0.5);
+    assert!(mid.sorted_entries() == vec![((0, 0), 3.0), ((0, 1), 4.0)]);
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_lerp_out_of_range() {
+    let a = SparseMatrix::identity(2);
+    let _ = a.lerp(&a, 1.5);
+}
+
+#[test]
+fn sparsemat_matvec_into() {
+    let local = SparseMatrix::random((5, 4), 0.5, 71);
+    let x = vec![1.0, -2.0, 0.5, 3.0];
+    let alpha = 0.75;
+
+    let mut y = vec![0.0; 5];
+    local.matvec_into(alpha, &x, &mut y);
+    local.matvec_into(alpha, &x, &mut y);
+
+    let single = local.matvec(&x);
+    let expected: Vec<f64> = single.iter().map(|v| alpha * v + alpha * v).collect();
+    assert!(std::iter::zip(&y, &expected).all(|(a, e)| (a - e).abs() < 1e-12));
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn sparsemat_matvec_into_bad_output() {
+    let local = SparseMatrix::identity(3);
+    let mut y = vec![0.0; 2];
+    local.matvec_into(1.0, &[1.0, 1.0, 1.0], &mut y);
+}
+
+#[test]
+fn sparsemat_incremental_compress() {
+    let n = 40;
+    let mut local = SparseMatrix::empty_with_shape(n, n);
+    for row in 0..n {
+        local.insert(row, row, 2.0 + row as f64);
+        local.insert(row, (row * 7 + 3) % n, -1.0);
+    }
+    local.explicitly_compress();
+
+    let assert_matches_full_rebuild = |matrix: &SparseMatrix| {
+        let mut rebuilt = SparseMatrix::empty_with_shape(n, n);
+        for ((row, col), val) in matrix.sorted_entries() {
+            rebuilt.insert(row, col, val);
+        }
+        rebuilt.explicitly_compress();
+        assert!(matrix.compressed_rowarray == rebuilt.compressed_rowarray);
+        assert!(matrix.compressed_colarray == rebuilt.compressed_colarray);
+        assert!(matrix.compressed_dataarray == rebuilt.compressed_dataarray);
+    };
+
+    // A few rows dirty: grow one, shrink another, leave the rest to be copied over
+    local.insert(1, 0, 5.0);
+    local.insert(1, 39, 6.0);
+    local.clear_at(2, 2);
+    local.update_at(30, 30, |v| v * 10.0);
+    local.explicitly_compress();
+    assert_matches_full_rebuild(&local);
+    assert!(local.compressed_rowarray[n as usize] == 2 * n + 1);
+
+    // Most rows dirty, as after a bulk insert_triplets on a compressed matrix
+    local.insert_triplets((0..n).map(|row| (row, (row + 1) % n, 0.5)).collect());
+    local.clear_at(5, 6);
+    local.explicitly_compress();
+    assert_matches_full_rebuild(&local);
+
+    // An untracked edit between tracked ones still yields the full rebuild
+    local.insert(0, 20, 1.0);
+    local.scale(2.0);
+    local.insert(3, 21, 1.0);
+    local.explicitly_compress();
+    assert_matches_full_rebuild(&local);
+    assert!(local.peek_at(0, 20) == Some(2.0));
+}
+
+#[test]
+fn sparsemat_zero_dimension() {
+    let mut local = SparseMatrix::new();
+    assert!(local.row_iter().count() == 0);
+    assert!(local.to_dense().is_empty());
+    assert!(local.matvec(&[]).is_empty());
+    assert!(local.frobenius_norm() == 0.0);
+    let _ = format!("{}", local);
+    assert!(local.trace() == 0.0);
+    assert!(local.is_symmetric());
+    assert!(&local * &SparseMatrix::identity(0) == local);
+    local.explicitly_compress();
+    assert!(local.compressed_rowarray == vec![0]);
+
+    // Zero rows but nonzero columns: still no rows to produce
+    let wide = SparseMatrix::empty_with_shape(0, 3);
+    assert!(wide.matvec(&[1.0, 2.0, 3.0]).is_empty());
+    assert!(wide.matvec_transpose(&[]) == vec![0.0; 3]);
+    assert!(wide.to_dense().is_empty());
+
+    let tall = SparseMatrix::empty_with_shape(3, 0);
+    assert!(tall.matvec(&[]) == vec![0.0; 3]);
+    assert!(tall.row_iter().count() == 3);
+    assert!(tall.to_dense() == vec![Vec::<f64>::new(); 3]);
+    assert!(tall.density() == 0.0);
+}
+
+#[test]
+fn sparsemat_row_iter_stale_cache() {
+    let mut local = SparseMatrix::empty_with_shape(3, 2);
+    local.insert_triplets(vec![(0, 1, 1.0), (2, 0, 2.0)]);
+    let rows: Vec<Vec<f64>> = local.row_iter().collect();
+    assert!(rows == vec![vec![0.0, 1.0], vec![0.0, 0.0], vec![2.0, 0.0]]);
+
+    // An edit after compressing shows up without another explicit compress
+    local.explicitly_compress();
+    local.insert(1, 1, 3.0);
+    let rows: Vec<Vec<f64>> = local.row_iter_range(1..3).collect();
+    assert!(rows == vec![vec![0.0, 3.0], vec![2.0, 0.0]]);
+}
+
+#[test]
+fn sparsemat_add_dense() {
+    let mut local = SparseMatrix::empty_with_shape(2, 2);
+    local.insert(0, 0, 1.0);
+    local.insert(1, 0, 3.0);
+
+    local.add_dense(&[vec![2.0, 5.0], vec![-3.0, 0.0]]);
+
+    assert!(local.peek_at(0, 0) == Some(3.0));
+    assert!(local.peek_at(0, 1) == Some(5.0));
+    // 3.0 + -3.0 cancels and is dropped rather than stored as a zero
+    assert!(local.peek_at(1, 0).is_none());
+    assert!(local.peek_at(1, 1).is_none());
+    assert!(local.num_nonzero() == 2);
+}
+
+#[test]
+#[should_panic]
+fn sparsemat_add_dense_bad_shape() {
+    let mut local = SparseMatrix::empty_with_shape(2, 2);
+    local.add_dense(&[vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+}
+
+#[test]
+fn sparsemat_estimate_product_nnz() {
+    let mut left = SparseMatrix::empty_with_shape(4, 6);
+    left.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (1, 3, 40.0),
+        (2, 2, 50.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.0),
+    ]);
+    let right = left.create_transpose();
+
+    let product = &left * &right;
+    assert!(left.estimate_product_nnz(&right) == product.num_nonzero());
+    assert!(right.estimate_product_nnz(&left) == (&right * &left).num_nonzero());
+
+    assert!(left.try_mul_bounded(&right, product.num_nonzero()).unwrap() == product);
+    assert!(
+        left.try_mul_bounded(&right, 3).err()
+            == Some(SparseError::TooManyNonzeros {
+                estimate: product.num_nonzero(),
+                limit: 3
+            })
+    );
+    assert!(
+        left.try_mul_bounded(&left, 100).err()
+            == Some(SparseError::DimensionMismatch {
+                expected: 6,
+                found: 4
+            })
+    );
+}
+
+#[test]
+fn sparsemat_to_column_stochastic() {
+    // Column 3 links nowhere (a dangling node)
+    let mut local = SparseMatrix::empty_with_shape(4, 4);
+    local.insert_triplets(vec![
+        (1, 0, 1.0),
+        (2, 0, 3.0),
+        (0, 1, 2.0),
+        (0, 2, 1.0),
+        (1, 2, 1.0),
+        (3, 2, 2.0),
+    ]);
+
+    let kept = local.to_column_stochastic(false);
+    let dense = kept.to_dense();
+    for col in 0..3 {
+        let sum: f64 = dense.iter().map(|row| row[col]).sum();
+        assert!((sum - 1.0).abs() < 1e-12);
+    }
+    assert!(kept.peek_at(2, 0) == Some(0.75));
+    assert!(dense.iter().all(|row| row[3] == 0.0));
+
+    let uniform = local.to_column_stochastic(true);
+    let dense = uniform.to_dense();
+    for col in 0..4 {
+        let sum: f64 = dense.iter().map(|row| row[col]).sum();
+        assert!((sum - 1.0).abs() < 1e-12);
+    }
+    assert!(uniform.peek_at(0, 3) == Some(0.25));
+
+    // A fully stochastic matrix preserves the total mass of a distribution
+    let next = uniform.matvec(&[0.25; 4]);
+    assert!((next.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn sparsemat_max_abs_diff() {
+    let mut left = SparseMatrix::empty_with_shape(3, 3);
+    left.insert_triplets(vec![(0, 0, 1.0), (1, 2, -2.0), (2, 1, 4.0)]);
+    let mut right = left.clone();
+    right.insert(1, 2, -2.5);
+
+    assert!(left.max_abs_diff(&right) == 0.5);
+    assert!(left.max_abs_diff(&left) == 0.0);
+    assert!(left.approx_eq(&right, 0.5));
+    assert!(!left.approx_eq(&right, 0.25));
+
+    // An entry present on one side only is compared against 0.0
+    right.insert(2, 2, -3.0);
+    assert!(left.max_abs_diff(&right) == 3.0);
+    assert!(right.max_abs_diff(&left) == 3.0);
+
+    // A NaN difference propagates rather than being dropped by the max
+    let mut broken = left.clone();
+    broken.insert(0, 0, f64::NAN);
+    assert!(broken.max_abs_diff(&left).is_nan());
+    assert!(left.max_abs_diff(&broken).is_nan());
+    assert!(!broken.approx_eq(&left, 0.0));
+    assert!(!broken.approx_eq(&broken, f64::INFINITY));
+}
+
+#[test]
+#[should_panic]
+fn sparsemat_max_abs_diff_shape_mismatch() {
+    let left = SparseMatrix::identity(2);
+    let right = SparseMatrix::identity(3);
+    left.max_abs_diff(&right);
+}
+
+#[test]
+fn sparsemat_clear_many() {
+    let mut local = SparseMatrix::empty_with_shape(4, 6);
+    local.insert_triplets(vec![
+        (0, 0, 10.0),
+        (0, 1, 20.0),
+        (1, 1, 30.0),
+        (1, 3, 40.0),
+        (2, 2, 50.0),
+        (2, 3, 60.0),
+        (2, 4, 70.0),
+        (3, 5, 80.0),
+    ]);
+    local.explicitly_compress();
+
+    assert!(local.clear_many(&[(0, 1), (3, 0), (2, 3)]) == 2);
+    assert!(local.num_nonzero() == 6);
+    assert!(local.peek_at(0, 1).is_none());
+    assert!(local.peek_at(2, 3).is_none());
+
+    local.explicitly_compress();
+    assert!(local.compressed_rowarray == vec![0, 1, 3, 5, 6]);
+}
+
+#[test]
+#[should_panic]
+fn sparsemat_clear_many_out_of_bounds() {
+    let mut local = SparseMatrix::identity(3);
+    local.clear_many(&[(0, 0), (3, 1)]);
+}